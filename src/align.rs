@@ -0,0 +1,127 @@
+use std::ops::Range;
+
+/// An edit turning the right answer into the user's one.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Edit {
+    /// The char of the right answer is typed correctly
+    Equal(char),
+    /// The user typed an extra char
+    Insert(char),
+    /// The user missed the char of the right answer
+    Delete(char),
+}
+
+/// Part of the user's answer that corresponds to an orthogram of the right answer.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Filling {
+    pub text: String,
+    pub right: bool,
+}
+
+/// Align the user's answer with the right one char by char using the longest common
+/// subsequence of them.
+pub fn align(answer: &str, right_answer: &str) -> Vec<Edit> {
+    let answer: Vec<char> = answer.chars().collect();
+    let right_answer: Vec<char> = right_answer.chars().collect();
+    let (n, m) = (answer.len(), right_answer.len());
+
+    // lcs[i][j] is the length of LCS of `answer[i..]` and `right_answer[j..]`
+    let mut lcs = vec![vec![0usize; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lcs[i][j] = if answer[i] == right_answer[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                std::cmp::max(lcs[i + 1][j], lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut edits = Vec::with_capacity(std::cmp::max(n, m));
+    let (mut i, mut j) = (0, 0);
+    while i < n && j < m {
+        if answer[i] == right_answer[j] && lcs[i][j] == lcs[i + 1][j + 1] + 1 {
+            edits.push(Edit::Equal(answer[i]));
+            i += 1;
+            j += 1;
+        } else if lcs[i + 1][j] >= lcs[i][j + 1] {
+            edits.push(Edit::Insert(answer[i]));
+            i += 1;
+        } else {
+            edits.push(Edit::Delete(right_answer[j]));
+            j += 1;
+        }
+    }
+    edits.extend(answer[i..].iter().map(|c| Edit::Insert(*c)));
+    edits.extend(right_answer[j..].iter().map(|c| Edit::Delete(*c)));
+    edits
+}
+
+/// Find what the user typed in place of every char range of the right answer, and
+/// whether it is right. Extra chars typed just at the edge of a range are considered
+/// as a part of the range.
+pub fn grade(answer: &str, right_answer: &str, ranges: &[Range<usize>]) -> Vec<Filling> {
+    let mut fillings: Vec<Filling> = ranges.iter().map(|_| Filling { text: String::new(), right: true }).collect();
+    let mut pos = 0;
+    for edit in align(answer, right_answer) {
+        for (range, filling) in ranges.iter().zip(fillings.iter_mut()) {
+            match edit {
+                Edit::Equal(c) if range.contains(&pos) => filling.text.push(c),
+                Edit::Delete(_) if range.contains(&pos) => filling.right = false,
+                Edit::Insert(c) if range.start <= pos && pos <= range.end => {
+                    filling.text.push(c);
+                    filling.right = false;
+                },
+                _ => ()
+            }
+        }
+        if !matches!(edit, Edit::Insert(_)) {
+            pos += 1;
+        }
+    }
+    fillings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn align_answers() {
+        assert_eq!(align("ab", "ab"), vec![Edit::Equal('a'), Edit::Equal('b')]);
+        assert_eq!(align("", "a"), vec![Edit::Delete('a')]);
+        assert_eq!(align("a", ""), vec![Edit::Insert('a')]);
+        assert_eq!(align("pece", "piece"), vec![Edit::Equal('p'), Edit::Delete('i'), Edit::Equal('e'),
+                                                Edit::Equal('c'), Edit::Equal('e')]);
+    }
+
+    #[test]
+    fn grade_orthograms() {
+        // a p[ie]ce of the c[a]ke
+        let ranges = [3..5, 16..17];
+        let fillings = grade("a piece of the cake", "a piece of the cake", &ranges);
+        assert_eq!(fillings, vec![Filling { text: "ie".into(), right: true },
+                                  Filling { text: "a".into(), right: true }]);
+
+        let fillings = grade("a peice of teh cake", "a piece of the cake", &ranges);
+        assert!(!fillings[0].right);
+        assert!(fillings[1].right);
+
+        let fillings = grade("a piece of the coke", "a piece of the cake", &ranges);
+        assert!(fillings[0].right);
+        assert_eq!(fillings[1], Filling { text: "o".into(), right: false });
+    }
+
+    #[test]
+    fn grade_empty_orthogram() {
+        // some[]thing[]
+        let gaps = [4..4, 9..9];
+        let fillings = grade("something", "something", &gaps);
+        assert_eq!(fillings[0], Filling { text: String::new(), right: true });
+        assert_eq!(fillings[1], Filling { text: String::new(), right: true });
+        let fillings = grade("some things", "something", &gaps);
+        assert_eq!(fillings[0], Filling { text: " ".into(), right: false });
+        assert_eq!(fillings[1], Filling { text: "s".into(), right: false });
+    }
+}
@@ -1,11 +1,13 @@
-use clap::Parser;
+use clap::{Parser, ValueEnum};
+use miette::{MietteDiagnostic, Result};
 use std::path::PathBuf;
 
 /// Program for testing your orthography knowledge
 #[derive(Parser, Debug)]
 #[command(version, about)]
 pub struct Args {
-    /// List of tort scripts to run
+    /// List of tort scripts to run. A script can have a weight like `lesson.tort:50%`,
+    /// that is its share of questions limited with `-n`
     #[arg(required=true, num_args(1..))]
     pub files: Vec<PathBuf>,
 
@@ -19,5 +21,168 @@ pub struct Args {
 
     /// how many tests you want to pass (0 means every test)
     #[arg(short, long)]
-    pub number_of_tests: Option<usize>
+    pub number_of_tests: Option<usize>,
+
+    /// How to answer orthogram lines (line by default)
+    #[arg(short, long, value_enum)]
+    pub answer: Option<AnswerMode>,
+
+    /// Rules of answer comparison, separated by commas
+    #[arg(long, value_enum, value_delimiter = ',')]
+    pub compare: Option<Vec<CompareRule>>,
+
+    /// Rules of answer comparison for plain lines (override `--compare`)
+    #[arg(long, value_enum, value_delimiter = ',')]
+    pub compare_plain: Option<Vec<CompareRule>>,
+
+    /// Rules of answer comparison for translation lines (override `--compare`)
+    #[arg(long, value_enum, value_delimiter = ',')]
+    pub compare_translation: Option<Vec<CompareRule>>,
+
+    /// Rules of answer comparison for orthogram lines (override `--compare`)
+    #[arg(long, value_enum, value_delimiter = ',')]
+    pub compare_complex: Option<Vec<CompareRule>>,
+
+    /// How many typos in a translation are forgiven as an "almost right" answer
+    #[arg(long)]
+    pub typos: Option<usize>,
+
+    /// Ask lines written with `<->` in reverse direction, from translation to original
+    #[arg(long, conflicts_with = "both")]
+    pub reverse: bool,
+
+    /// Ask lines written with `<->` in random direction
+    #[arg(long)]
+    pub both: bool,
+
+    /// Ask wrongly answered tests again until they are answered right
+    #[arg(long)]
+    pub retry_wrong: bool,
+
+    /// When explanations of lines are shown after the answer
+    #[arg(long, value_enum)]
+    pub explain: Option<ExplainMode>,
+
+    /// Ask only tests that are due to be repeated, plus a few new ones
+    #[arg(short, long)]
+    pub due: bool,
+
+    /// How many new tests can be introduced per day in `--due` mode
+    #[arg(long, default_value_t = 10)]
+    pub new_per_day: usize,
+
+    /// File to keep your progress in (by default it is in your data directory)
+    #[arg(long)]
+    pub progress: Option<PathBuf>,
+
+    /// Run only sections whose headings contain the text (can be used several times)
+    #[arg(short, long)]
+    pub section: Vec<String>,
+
+    /// Print sections of the scripts instead of running them
+    #[arg(long)]
+    pub list_sections: bool,
+
+    /// Run only statements with the tag; `a+b` requires both tags, and several options
+    /// are alternatives
+    #[arg(short, long)]
+    pub tag: Vec<String>,
+
+    /// Skip statements with the tag (can be used several times)
+    #[arg(long)]
+    pub exclude_tag: Vec<String>,
+
+    /// Mix questions of several scripts instead of running them one by one
+    #[arg(long)]
+    pub interleave: bool,
+}
+
+impl Args {
+    /// Input files with their weights given as `file.tort:50%`.
+    pub fn weighted_files(&self) -> Result<Vec<(PathBuf, Option<usize>)>> {
+        let mut files = Vec::new();
+        let mut sum = 0;
+        for file in &self.files {
+            let file_name = file.to_string_lossy();
+            let Some((path, weight)) = aid::split_weight(&file_name) else {
+                files.push((file.clone(), None));
+                continue;
+            };
+            if !(1..=100).contains(&weight) {
+                return Err(MietteDiagnostic::new(format!("weight of `{path}` must be from 1% to 100%"))
+                    .with_severity(miette::Severity::Error).into());
+            }
+            sum += weight;
+            files.push((PathBuf::from(path), Some(weight)));
+        }
+        if sum > 100 {
+            return Err(MietteDiagnostic::new(format!("weights of the scripts sum to {sum}%, that is more than 100%"))
+                .with_severity(miette::Severity::Error).into());
+        }
+        Ok(files)
+    }
+
+    /// Direction of translation specified by `--reverse` or `--both` flags.
+    pub fn direction(&self) -> Option<Direction> {
+        if self.reverse {
+            Some(Direction::Reverse)
+        } else if self.both {
+            Some(Direction::Both)
+        } else {
+            None
+        }
+    }
+}
+
+/// Direction in which bidirectional translation lines are asked.
+#[derive(ValueEnum, Debug, PartialEq, Clone, Copy)]
+pub enum Direction {
+    /// From original to translation
+    Forward,
+    /// From translation to original
+    Reverse,
+    /// In random direction
+    Both,
+}
+
+#[derive(ValueEnum, Debug, PartialEq, Clone, Copy)]
+pub enum AnswerMode {
+    /// Type the whole line
+    Line,
+    /// Type only contents of the orthograms, one by one
+    Gaps,
+    /// Select variants of choice orthograms by their numbers, like `2; 1; 3`
+    Select,
+}
+
+#[derive(ValueEnum, Debug, PartialEq, Clone, Copy)]
+pub enum ExplainMode {
+    /// After every answer
+    Always,
+    /// Only after a wrong answer (the default)
+    Wrong,
+}
+
+#[derive(ValueEnum, Debug, PartialEq, Clone, Copy)]
+pub enum CompareRule {
+    /// Answers must be exactly equal (the default)
+    Strict,
+    /// All the rules below
+    Lenient,
+    /// Ignore letter case
+    Case,
+    /// Ignore leading, trailing and repeated whitespaces
+    Space,
+    /// Ignore punctuation
+    Punct,
+    /// Ignore diacritics
+    Marks,
+}
+
+pub(super) mod aid {
+    /// Split `file.tort:50%` into the path and the weight.
+    pub fn split_weight(file: &str) -> Option<(&str, usize)> {
+        let (path, weight) = file.strip_suffix('%')?.rsplit_once(':')?;
+        Some((path, weight.parse().ok()?))
+    }
 }
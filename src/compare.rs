@@ -0,0 +1,219 @@
+use crate::args::CompareRule;
+use crate::settings::Settings;
+
+/// Policy of comparison of the user's answer with the right one. Strict policy
+/// (the default one) requires answers to be exactly equal.
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub struct Policy {
+    pub ignore_case: bool,
+    pub collapse_spaces: bool,
+    pub ignore_punct: bool,
+    pub ignore_marks: bool,
+}
+
+impl Policy {
+    pub fn strict() -> Self {
+        Self::default()
+    }
+
+    pub fn lenient() -> Self {
+        Self {
+            ignore_case: true,
+            collapse_spaces: true,
+            ignore_punct: true,
+            ignore_marks: true,
+        }
+    }
+
+    /// Build policy applying rules one by one, so `lenient,strict` means strict.
+    pub fn new(rules: &[CompareRule]) -> Self {
+        let mut policy = Self::strict();
+        for rule in rules {
+            match rule {
+                CompareRule::Strict => policy = Self::strict(),
+                CompareRule::Lenient => policy = Self::lenient(),
+                CompareRule::Case => policy.ignore_case = true,
+                CompareRule::Space => policy.collapse_spaces = true,
+                CompareRule::Punct => policy.ignore_punct = true,
+                CompareRule::Marks => policy.ignore_marks = true,
+            }
+        }
+        policy
+    }
+
+    /// Bring the text to the form where differences ignored by the policy disappear.
+    pub fn normalize(&self, text: &str) -> String {
+        let mut normalized = String::with_capacity(text.len());
+        let mut chars = text.chars().peekable();
+        while let Some(c) = chars.next() {
+            // letters like `ў` typed with a combining mark are kept as distinct letters
+            let c = match chars.peek().and_then(|mark| aid::compose_letter(c, *mark)) {
+                Some(letter) => {
+                    chars.next();
+                    letter
+                },
+                None => c
+            };
+            if self.ignore_marks && aid::is_combining_mark(c) {
+                continue;
+            }
+            if self.ignore_punct && !c.is_alphanumeric() && !c.is_whitespace() && !aid::is_combining_mark(c) {
+                continue;
+            }
+            let c = if self.ignore_marks { aid::strip_diacritic(c) } else { c };
+            if self.ignore_case {
+                normalized.extend(c.to_lowercase());
+            } else {
+                normalized.push(c);
+            }
+        }
+        if self.collapse_spaces {
+            normalized.split_whitespace().collect::<Vec<_>>().join(" ")
+        } else {
+            normalized
+        }
+    }
+
+    pub fn matches(&self, answer: &str, right_answer: &str) -> bool {
+        answer == right_answer || self.normalize(answer) == self.normalize(right_answer)
+    }
+
+    /// Damerau-Levenshtein distance between the normalized answers.
+    pub fn distance(&self, answer: &str, right_answer: &str) -> usize {
+        strsim::damerau_levenshtein(&self.normalize(answer), &self.normalize(right_answer))
+    }
+}
+
+/// Comparison policies for every kind of statements.
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub struct Policies {
+    pub plain: Policy,
+    pub translation: Policy,
+    pub complex: Policy,
+}
+
+impl Policies {
+    /// Override the policies specified in the settings: `compare` sets all of them,
+    /// and `compare-*` ones set the policy of a certain kind of statements.
+    pub fn update(&mut self, settings: &Settings) {
+        if let Some(rules) = &settings.compare {
+            let policy = Policy::new(rules);
+            *self = Self { plain: policy, translation: policy, complex: policy };
+        }
+        if let Some(rules) = &settings.compare_plain {
+            self.plain = Policy::new(rules);
+        }
+        if let Some(rules) = &settings.compare_translation {
+            self.translation = Policy::new(rules);
+        }
+        if let Some(rules) = &settings.compare_complex {
+            self.complex = Policy::new(rules);
+        }
+    }
+}
+
+pub(super) mod aid {
+    pub fn is_combining_mark(c: char) -> bool {
+        matches!(c, '\u{0300}'..='\u{036F}' | '\u{1AB0}'..='\u{1AFF}' | '\u{1DC0}'..='\u{1DFF}' |
+                    '\u{20D0}'..='\u{20FF}' | '\u{FE20}'..='\u{FE2F}')
+    }
+
+    /// Letters of Cyrillic alphabets that look like other letters with a diacritic, but
+    /// are distinct letters, composed of their base letter and the combining mark.
+    pub fn compose_letter(base: char, mark: char) -> Option<char> {
+        match (base, mark) {
+            ('у', '\u{306}') => Some('ў'),
+            ('У', '\u{306}') => Some('Ў'),
+            ('и', '\u{306}') => Some('й'),
+            ('И', '\u{306}') => Some('Й'),
+            ('е', '\u{308}') => Some('ё'),
+            ('Е', '\u{308}') => Some('Ё'),
+            ('і', '\u{308}') => Some('ї'),
+            ('І', '\u{308}') => Some('Ї'),
+            _ => None
+        }
+    }
+
+    /// Replace a precomposed Latin letter with diacritic by its base letter. Cyrillic
+    /// letters like `ў` or `й` are distinct letters, so they are left as they are.
+    pub fn strip_diacritic(c: char) -> char {
+        match c {
+            'à' | 'á' | 'â' | 'ã' | 'ä' | 'å' | 'ā' | 'ă' | 'ą' => 'a',
+            'À' | 'Á' | 'Â' | 'Ã' | 'Ä' | 'Å' | 'Ā' | 'Ă' | 'Ą' => 'A',
+            'ç' | 'ć' | 'ĉ' | 'ċ' | 'č' => 'c',
+            'Ç' | 'Ć' | 'Ĉ' | 'Ċ' | 'Č' => 'C',
+            'ď' | 'đ' => 'd',
+            'Ď' | 'Đ' => 'D',
+            'è' | 'é' | 'ê' | 'ë' | 'ē' | 'ĕ' | 'ė' | 'ę' | 'ě' => 'e',
+            'È' | 'É' | 'Ê' | 'Ë' | 'Ē' | 'Ĕ' | 'Ė' | 'Ę' | 'Ě' => 'E',
+            'ĝ' | 'ğ' | 'ġ' | 'ģ' => 'g',
+            'Ĝ' | 'Ğ' | 'Ġ' | 'Ģ' => 'G',
+            'ì' | 'í' | 'î' | 'ï' | 'ĩ' | 'ī' | 'ĭ' | 'į' => 'i',
+            'Ì' | 'Í' | 'Î' | 'Ï' | 'Ĩ' | 'Ī' | 'Ĭ' | 'Į' | 'İ' => 'I',
+            'ĺ' | 'ļ' | 'ľ' | 'ł' => 'l',
+            'Ĺ' | 'Ļ' | 'Ľ' | 'Ł' => 'L',
+            'ñ' | 'ń' | 'ņ' | 'ň' => 'n',
+            'Ñ' | 'Ń' | 'Ņ' | 'Ň' => 'N',
+            'ò' | 'ó' | 'ô' | 'õ' | 'ö' | 'ø' | 'ō' | 'ŏ' | 'ő' => 'o',
+            'Ò' | 'Ó' | 'Ô' | 'Õ' | 'Ö' | 'Ø' | 'Ō' | 'Ŏ' | 'Ő' => 'O',
+            'ŕ' | 'ř' => 'r',
+            'Ŕ' | 'Ř' => 'R',
+            'ś' | 'ŝ' | 'ş' | 'š' => 's',
+            'Ś' | 'Ŝ' | 'Ş' | 'Š' => 'S',
+            'ţ' | 'ť' => 't',
+            'Ţ' | 'Ť' => 'T',
+            'ù' | 'ú' | 'û' | 'ü' | 'ũ' | 'ū' | 'ŭ' | 'ů' | 'ű' | 'ų' => 'u',
+            'Ù' | 'Ú' | 'Û' | 'Ü' | 'Ũ' | 'Ū' | 'Ŭ' | 'Ů' | 'Ű' | 'Ų' => 'U',
+            'ý' | 'ÿ' => 'y',
+            'Ý' | 'Ÿ' => 'Y',
+            'ź' | 'ż' | 'ž' => 'z',
+            'Ź' | 'Ż' | 'Ž' => 'Z',
+            _ => c,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn strict_policy() {
+        let policy = Policy::strict();
+        assert!(policy.matches("Hello", "Hello"));
+        assert!(!policy.matches("hello", "Hello"));
+        assert!(!policy.matches("Hello ", "Hello"));
+    }
+
+    #[test]
+    fn policy_rules() {
+        let policy = Policy::new(&[CompareRule::Case, CompareRule::Space]);
+        assert!(policy.matches(" hello   world ", "Hello world"));
+        assert!(!policy.matches("hello world!", "Hello world"));
+
+        let policy = Policy::new(&[CompareRule::Punct]);
+        assert!(policy.matches("Hello world", "Hello, world!"));
+
+        let policy = Policy::new(&[CompareRule::Marks]);
+        assert!(policy.matches("малако", "малако\u{301}"));
+        assert!(policy.matches("cafe", "café"));
+        assert!(policy.matches("cafe", "cafe\u{301}"));
+
+        assert_eq!(Policy::new(&[CompareRule::Lenient, CompareRule::Strict]), Policy::strict());
+        assert_eq!(Policy::new(&[CompareRule::Case]).distance("Recieve", "receive"), 1);
+        assert_eq!(Policy::new(&[CompareRule::Lenient]).normalize(" Ёлка, "), "ёлка");
+    }
+
+    #[test]
+    fn marks_keep_distinct_letters() {
+        let policy = Policy::new(&[CompareRule::Marks]);
+        assert!(!policy.matches("воук", "воўк"));
+        assert!(!policy.matches("елка", "ёлка"));
+        assert!(!policy.matches("мои", "мой"));
+        assert!(!policy.matches("іх", "їх"));
+        assert!(policy.matches("воу\u{306}к", "воўк"));
+        assert!(policy.matches("во\u{301}ўк", "воўк"));
+        assert!(!Policy::new(&[CompareRule::Lenient]).matches("ВОУК", "воўк"));
+    }
+}
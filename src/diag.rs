@@ -31,6 +31,104 @@ impl<'source> Diag<'source> {
         report.with_source_code(NamedSource::new(self.source_name.clone(), self.source.to_owned()))
     }
 
+    pub fn unknown_escape(&self, escape: &str, escape_snap: SourceRange, known: &[&str]) -> Report {
+        let msg = format!("unknown escape sequence `{escape}`");
+        let known: Vec<String> = known.iter().map(|seq| format!("`\\{seq}`")).collect();
+        let report: Report = MietteDiagnostic::new(msg)
+            .with_label(LabeledSpan::new_with_span(Some("the unknown escape sequence".to_owned()), escape_snap))
+            .with_help(format!("known escape sequences are {}", known.join(", ")))
+            .with_severity(Severity::Error).into();
+        report.with_source_code(NamedSource::new(self.source_name.clone(), self.source.to_owned()))
+    }
+
+    pub fn invalid_directive(&self, token: Token, msg: &str) -> Report {
+        let msg = format!("invalid directive: {msg}");
+        let report: Report = MietteDiagnostic::new(msg)
+            .with_label(LabeledSpan::new_with_span(Some("the directive".to_owned()), token.span()))
+            .with_severity(Severity::Error).into();
+        report.with_source_code(NamedSource::new(self.source_name.clone(), self.source.to_owned()))
+    }
+
+    pub fn unreadable_include(&self, directive_snap: SourceRange, error: &std::io::Error) -> Report {
+        let msg = format!("can't read the included script: {error}");
+        let report: Report = MietteDiagnostic::new(msg)
+            .with_label(LabeledSpan::new_with_span(Some("the include directive".to_owned()), directive_snap))
+            .with_severity(Severity::Error).into();
+        report.with_source_code(NamedSource::new(self.source_name.clone(), self.source.to_owned()))
+    }
+
+    pub fn include_cycle(&self, directive_snap: SourceRange, cycle: &[String]) -> Report {
+        let report: Report = MietteDiagnostic::new("the script includes itself")
+            .with_label(LabeledSpan::new_with_span(Some("the include directive".to_owned()), directive_snap))
+            .with_help(format!("scripts include each other in a cycle: {}", cycle.join(" -> ")))
+            .with_severity(Severity::Error).into();
+        report.with_source_code(NamedSource::new(self.source_name.clone(), self.source.to_owned()))
+    }
+
+    pub fn invalid_orthogram(&self, orthogram_snap: SourceRange, msg: &str) -> Report {
+        let msg = format!("invalid orthogram: {msg}");
+        let report: Report = MietteDiagnostic::new(msg)
+            .with_label(LabeledSpan::new_with_span(Some("the orthogram".to_owned()), orthogram_snap))
+            .with_severity(Severity::Error).into();
+        report.with_source_code(NamedSource::new(self.source_name.clone(), self.source.to_owned()))
+    }
+
+    pub fn invalid_segments(&self, word_span: SourceRange, msg: &str) -> Report {
+        let msg = format!("invalid segmented word: {msg}");
+        let report: Report = MietteDiagnostic::new(msg)
+            .with_label(LabeledSpan::new_with_span(Some("the word".to_owned()), word_span))
+            .with_severity(Severity::Error).into();
+        report.with_source_code(NamedSource::new(self.source_name.clone(), self.source.to_owned()))
+    }
+
+    pub fn invalid_choice(&self, question_span: SourceRange, msg: &str) -> Report {
+        let msg = format!("invalid choice question: {msg}");
+        let report: Report = MietteDiagnostic::new(msg)
+            .with_label(LabeledSpan::new_with_span(Some("the question".to_owned()), question_span))
+            .with_severity(Severity::Error).into();
+        report.with_source_code(NamedSource::new(self.source_name.clone(), self.source.to_owned()))
+    }
+
+    pub fn one_way_orthograms(&self, arrow: Token) -> Report {
+        let msg = "orthograms of the original are never asked in a line with `->`, use `<->` to ask it in reverse";
+        let report: Report = MietteDiagnostic::new(msg)
+            .with_label(LabeledSpan::new_with_span(Some("the one-way arrow".to_owned()), arrow.span()))
+            .with_severity(Severity::Error).into();
+        report.with_source_code(NamedSource::new(self.source_name.clone(), self.source.to_owned()))
+    }
+
+    pub fn invalid_stress(&self, token: &Token, msg: &str) -> Report {
+        let msg = format!("invalid stress mark: {msg}");
+        let report: Report = MietteDiagnostic::new(msg)
+            .with_label(LabeledSpan::new_with_span(Some("the stressed word".to_owned()), token.span()))
+            .with_severity(Severity::Error).into();
+        report.with_source_code(NamedSource::new(self.source_name.clone(), self.source.to_owned()))
+    }
+
+    pub fn unknown_rule(&self, token: &Token) -> Report {
+        let msg = format!("unknown rule `{}`", token.spelling());
+        let report: Report = MietteDiagnostic::new(msg)
+            .with_label(LabeledSpan::new_with_span(Some("the rule reference".to_owned()), token.span()))
+            .with_help("rules are defined with `#!rule id = title` directives of the script or the scripts it includes")
+            .with_severity(Severity::Error).into();
+        report.with_source_code(NamedSource::new(self.source_name.clone(), self.source.to_owned()))
+    }
+
+    pub fn duplicate_rule(&self, span: SourceRange, id: &str, first_script: &str) -> Report {
+        let msg = format!("rule `@{id}` is defined twice");
+        let report: Report = MietteDiagnostic::new(msg)
+            .with_label(LabeledSpan::new_with_span(Some("the second definition".to_owned()), span))
+            .with_help(format!("it's already defined in `{first_script}`, give one of them another id"))
+            .with_severity(Severity::Error).into();
+        report.with_source_code(NamedSource::new(self.source_name.clone(), self.source.to_owned()))
+    }
+
+    /// Note for errors of an included script, pointing to its include directive.
+    pub fn included_from(&self, directive_snap: &SourceRange) -> String {
+        let line = self.source[..directive_snap.start].matches('\n').count() + 1;
+        format!("in a script included from {}:{line}", self.source_name)
+    }
+
     pub fn unexpected_eof(&self, eof_snap: SourceRange) -> Report {
         let report: Report = MietteDiagnostic::new("unexpected end of file")
             .with_label(LabeledSpan::new_with_span(Some("eof".to_owned()), eof_snap))
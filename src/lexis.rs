@@ -14,25 +14,59 @@ pub enum TokenKind {
     #[token("]", priority = 3)]
     r_square,
 
+    /// Opening of a word split into segments, like `{ма|ла|ко}`. Braces are markup
+    /// in every statement, `\{` and `\}` are written for usual ones
+    #[token("{", priority = 3)]
+    l_brace,
+
+    #[token("}", priority = 3)]
+    r_brace,
+
     #[token("->")]
     arrow,
 
+    #[token("<->")]
+    bi_arrow,
+
     #[token(":", priority = 3)]
     colon,
 
     #[token("|", priority = 3)]
     pipe,
 
+    /// Separator of right variants of an orthogram, it's a usual text outside orthograms
+    #[token("/", priority = 3)]
+    slash,
+
+    /// Backslash followed by a markup character, that makes it a usual text
+    #[regex(r"\\(->|<->|[^\n\r])?")]
+    escape,
+
     #[regex(r"#[^\n\r]*", priority=5)]
     comment,
 
     #[regex(r"#![^\n\r]*", priority=7)]
     pub_comment,
 
+    /// Explanation of a statement shown after the answer, like `#? rule`. A comment
+    /// or a public comment lasts till an explanation, see [`Lexer::lex`].
+    #[regex(r"#\?[^\n\r]*", priority=7)]
+    explanation,
+
+    /// Heading of a section, like `## Title`. Only the first token of a line can be
+    /// a heading, otherwise it's a comment.
+    #[regex(r"##+[ \t][^\n\r]*", priority=6)]
+    heading,
+
+    /// Tag of a statement, like `@verbs`. It's a usual text if it's glued to a
+    /// previous text or is inside an orthogram.
+    #[regex(r"@[\pL\pN_-]+")]
+    tag,
+
     #[regex(r"[\pL\pM]+")]
     word,
 
-    #[regex(r"[\pP--\[\]:]+")]
+    #[regex(r"[\pP--\[\]:\\/{}]+")]
     punct,
 
     #[regex(r"[\pN]+")]
@@ -55,18 +89,26 @@ impl std::fmt::Display for TokenKind {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let msg = match self {
             tok::arrow => "->",
+            tok::bi_arrow => "<->",
             tok::colon => ":",
             tok::comment => "<COMMENT>",
             tok::eof => "<EOF>",
+            tok::escape => "<ESCAPE>",
+            tok::heading => "<HEADING>",
             tok::l_square => "[",
             tok::r_square => "]",
+            tok::l_brace => "{",
+            tok::r_brace => "}",
             tok::newline => "<LF>",
             tok::number => "<NUM>",
             tok::other | &tok::word => "<WORD>",
             tok::punct => "<PUNCT>",
             tok::pipe => "|",
+            tok::slash => "/",
             tok::pub_comment => "<PUB-COMMENT>",
-            tok::space => "<WHITESPACE>"
+            tok::explanation => "<EXPLANATION>",
+            tok::space => "<WHITESPACE>",
+            tok::tag => "<TAG>"
         };
         write!(f, "{}", msg)
     }
@@ -76,6 +118,9 @@ pub mod tok {
     pub use super::TokenKind::*;
 }
 
+/// Markup sequences that can be escaped with a backslash.
+pub const ESCAPED: &[&str] = &["[", "]", "|", ":", "#", "->", "<->", "\\", "@", "/", "{", "}"];
+
 #[derive(Debug, PartialEq, Clone)]
 pub struct Token {
     kind: TokenKind,
@@ -90,9 +135,13 @@ impl Token {
             span,
             spelling: match tok_kind {
                 tok::word | tok::punct | tok::number | tok::other | tok::newline |
-                tok::l_square | tok::r_square | tok::arrow | tok::colon | tok::pipe => slice,
+                tok::l_square | tok::r_square | tok::arrow | tok::bi_arrow | tok::colon | tok::pipe | tok::slash |
+                tok::tag | tok::l_brace | tok::r_brace => slice,
+                tok::heading => slice.trim(),
+                tok::escape => &slice[1..],  // skip first \\
                 tok::comment => slice[1..].trim(),  // skip first #
                 tok::pub_comment => slice[2..].trim(),  // skip first #!
+                tok::explanation => slice[2..].trim(),  // skip first #?
                 tok::space => " ",
                 tok::eof => ""
             }.into()
@@ -120,17 +169,31 @@ impl Token {
         &self.spelling
     }
 
+    /// The text token without its first char, like `-` for `--`, or `None` if nothing
+    /// is left.
+    pub fn tail(&self) -> Option<Token> {
+        let first = self.spelling.chars().next()?.len_utf8();
+        let rest = self.spelling.get(first..).filter(|rest| !rest.is_empty())?;
+        Some(Token::new(self.kind, self.span.start + first..self.span.end, rest))
+    }
+
+    /// Whether the token is a shebang: a public comment at the start of a script
+    /// followed by an interpreter path, like `#!/usr/bin/env tort`.
+    pub fn is_shebang(&self) -> bool {
+        self.kind == tok::pub_comment && self.span.start == 0 && self.spelling.starts_with('/')
+    }
+
     pub fn is_text(&self) -> bool {
         match self.kind() {
-            tok::word | tok::punct | tok::number | tok::other |
-            tok::colon | tok::pipe | tok::space => true,
+            tok::word | tok::punct | tok::number | tok::other | tok::escape | tok::tag |
+            tok::colon | tok::pipe | tok::slash | tok::space => true,
             _ => false
         }
     }
 
     pub fn is_strict_text(&self) -> bool {
         match self.kind() {
-            tok::word | tok::punct | tok::number | tok::other | tok::space => true,
+            tok::word | tok::punct | tok::number | tok::other | tok::escape | tok::tag | tok::space => true,
             _ => false
         }
     }
@@ -154,6 +217,7 @@ pub struct Lexer<'source> {
     lexer: logos::Lexer<'source, TokenKind>,
     diag: Diag<'source>,
     skip_comments: bool,
+    line_start: bool,
 }
 
 impl<'source> Lexer<'source> {
@@ -162,6 +226,7 @@ impl<'source> Lexer<'source> {
             lexer: TokenKind::lexer(source),
             diag: Diag::new(source_name, source),
             skip_comments: true,
+            line_start: true,
         }
     }
 
@@ -172,12 +237,19 @@ impl<'source> Lexer<'source> {
     pub fn lex(&mut self) -> Result<Token> {
         let mut error_has_happened = false;
 
-        for res in &mut self.lexer {
+        while let Some(res) = self.lexer.next() {
             match res {
                 Ok(kind) => {
-                    if kind == tok::comment && self.skip_comments {
+                    let line_start = self.line_start;
+                    self.line_start = kind == tok::newline;
+                    let kind = if kind == tok::heading && !line_start { tok::comment } else { kind };
+                    // a comment with an explanation is kept, the parser splits it if it ends a statement
+                    if kind == tok::comment && self.skip_comments && !self.lexer.slice()[1..].contains("#?") {
                         continue;
                     }
+                    if kind == tok::escape && !ESCAPED.contains(&&self.lexer.slice()[1..]) {
+                        return Err(self.diag.unknown_escape(self.lexer.slice(), self.lexer.span(), ESCAPED));
+                    }
                     return Ok(Token::new(kind, self.lexer.span(), self.lexer.slice()));
                 }
                 Err(()) => {
@@ -215,6 +287,39 @@ impl<'source> Lexer<'source> {
         }
     }
 
+    /// Split the comment or public comment at the explanation it contains, like
+    /// `#! hint #? explanation`. The comment is returned as it is if it has none.
+    pub fn split_explanation(&self, token: Token) -> (Token, Option<Token>) {
+        let span = token.span();
+        let slice = &self.lexer.source()[span.clone()];
+        let Some(pos) = slice.get(1..).and_then(|rest| rest.find("#?")).filter(|_| token.is_comment()) else {
+            return (token, None);
+        };
+        let split = span.start + 1 + pos;
+        let explanation = Token::new(tok::explanation, split..span.end, &slice[1 + pos..]);
+        (Token::new(token.kind(), span.start..split, &slice[..1 + pos]), Some(explanation))
+    }
+
+    /// Whether only tags and spaces are left till the end of the line or a comment,
+    /// so that the last lexed tag is a tag of the statement rather than a usual text.
+    pub fn only_tags_left(&self) -> bool {
+        let mut lexer = self.lexer.clone();
+        loop {
+            match lexer.next() {
+                Some(Ok(tok::tag | tok::space)) => continue,
+                Some(Ok(tok::comment | tok::pub_comment | tok::explanation | tok::heading | tok::newline)) | None => {
+                    return true
+                },
+                Some(_) => return false
+            }
+        }
+    }
+
+    /// Byte offset in the source right after the last token.
+    pub fn offset(&self) -> usize {
+        self.lexer.span().end
+    }
+
     /// Consume token of kind `tok::newline` or `tok::eof`, else return error.
     pub fn expect_eol(&mut self) -> Result<()> {
         let token = self.lex()?;
@@ -264,6 +369,137 @@ mod tests {
         assert_eq!(lexer.next(), None);
     }
 
+    #[test]
+    fn lex_arrows() {
+        let mut lexer = TokenKind::lexer("a->b<->c <- d");
+        assert_eq!(lexer.next(), Some(Ok(tok::word)));
+        assert_eq!(lexer.next(), Some(Ok(tok::arrow)));
+        assert_eq!(lexer.next(), Some(Ok(tok::word)));
+        assert_eq!(lexer.next(), Some(Ok(tok::bi_arrow)));
+        assert_eq!(lexer.next(), Some(Ok(tok::word)));
+        assert_eq!(lexer.next(), Some(Ok(tok::space)));
+        assert_eq!(lexer.next(), Some(Ok(tok::other)));
+        assert_eq!(lexer.next(), Some(Ok(tok::punct)));
+        assert_eq!(lexer.next(), Some(Ok(tok::space)));
+        assert_eq!(lexer.next(), Some(Ok(tok::word)));
+        assert_eq!(lexer.next(), None);
+    }
+
+    #[test]
+    fn lex_slashes() {
+        let mut lexer = TokenKind::lexer("a/b ./,");
+        assert_eq!(lexer.next(), Some(Ok(tok::word)));
+        assert_eq!(lexer.next(), Some(Ok(tok::slash)));
+        assert_eq!(lexer.next(), Some(Ok(tok::word)));
+        assert_eq!(lexer.next(), Some(Ok(tok::space)));
+        assert_eq!(lexer.next(), Some(Ok(tok::punct)));
+        assert_eq!(lexer.next(), Some(Ok(tok::slash)));
+        assert_eq!(lexer.next(), Some(Ok(tok::punct)));
+        assert_eq!(lexer.next(), None);
+    }
+
+    #[test]
+    fn lex_tags() {
+        let mut lexer = TokenKind::lexer("word @verbs @hard_1 a@b @ \\@no");
+        assert_eq!(lexer.next(), Some(Ok(tok::word)));
+        assert_eq!(lexer.next(), Some(Ok(tok::space)));
+        assert_eq!(lexer.next(), Some(Ok(tok::tag)));
+        assert_eq!(lexer.slice(), "@verbs");
+        assert_eq!(lexer.next(), Some(Ok(tok::space)));
+        assert_eq!(lexer.next(), Some(Ok(tok::tag)));
+        assert_eq!(lexer.next(), Some(Ok(tok::space)));
+        assert_eq!(lexer.next(), Some(Ok(tok::word)));
+        assert_eq!(lexer.next(), Some(Ok(tok::tag)));
+        assert_eq!(lexer.next(), Some(Ok(tok::space)));
+        assert_eq!(lexer.next(), Some(Ok(tok::punct)));
+        assert_eq!(lexer.next(), Some(Ok(tok::space)));
+        assert_eq!(lexer.next(), Some(Ok(tok::escape)));
+        assert_eq!(lexer.next(), Some(Ok(tok::word)));
+        assert_eq!(lexer.next(), None);
+    }
+
+    #[test]
+    fn lex_escapes() {
+        let source = r"\[a\]\|\:\#\->\<->\\\@\/\{\}";
+        let mut lexer = Lexer::new("test", source);
+        for spelling in ESCAPED {
+            let token = lexer.lex().unwrap();
+            assert_eq!(token.kind(), tok::escape);
+            assert_eq!(token.spelling(), *spelling);
+            if *spelling == "[" {
+                assert_eq!(lexer.lex().unwrap().kind(), tok::word);
+            }
+        }
+        assert_eq!(lexer.lex().unwrap(), Token::eof());
+
+        let mut lexer = Lexer::new("test", ".\\#");
+        assert_eq!(lexer.lex().unwrap().kind(), tok::punct);
+        assert_eq!(lexer.lex().unwrap().kind(), tok::escape);
+    }
+
+    #[test]
+    fn lex_unknown_escapes() {
+        let source = "a\\n";
+        let mut lexer = Lexer::new("test", source);
+        let diag = Diag::new("test", source);
+        assert_eq!(lexer.lex().unwrap().kind(), tok::word);
+        assert_eq!(format!("{:?}", lexer.lex().unwrap_err()),
+                   format!("{:?}", diag.unknown_escape("\\n", 1..3, ESCAPED)));
+
+        let mut lexer = Lexer::new("test", "a\\");
+        assert_eq!(lexer.lex().unwrap().kind(), tok::word);
+        assert!(lexer.lex().is_err());
+    }
+
+    #[test]
+    fn lex_headings() {
+        let source = "## Title\n#### Subtitle \nword ## comment\n##no heading\n \t## comment";
+        let mut lexer = Lexer::new("test", source);
+        lexer.skip_comments(false);
+        let token = lexer.lex().unwrap();
+        assert_eq!(token.kind(), tok::heading);
+        assert_eq!(token.spelling(), "## Title");
+        assert_eq!(lexer.lex().unwrap().kind(), tok::newline);
+        assert_eq!(lexer.lex().unwrap().kind(), tok::heading);
+        assert_eq!(lexer.lex().unwrap().kind(), tok::newline);
+        assert_eq!(lexer.lex().unwrap().kind(), tok::word);
+        assert_eq!(lexer.lex().unwrap().kind(), tok::space);
+        assert_eq!(lexer.lex().unwrap().kind(), tok::comment);
+        assert_eq!(lexer.lex().unwrap().kind(), tok::newline);
+        assert_eq!(lexer.lex().unwrap().kind(), tok::comment);
+        assert_eq!(lexer.lex().unwrap().kind(), tok::newline);
+        assert_eq!(lexer.lex().unwrap().kind(), tok::space);
+        assert_eq!(lexer.lex().unwrap().kind(), tok::comment);
+        assert_eq!(lexer.lex().unwrap(), Token::eof());
+    }
+
+    #[test]
+    fn lex_explanations() {
+        let source = "a #! hint #1 #? explanation #! not a hint\n#? b # c #?d\n# e\n# f #? g";
+        let mut lexer = Lexer::new("test", source);
+        assert_eq!(lexer.lex().unwrap().kind(), tok::word);
+        assert_eq!(lexer.lex().unwrap().kind(), tok::space);
+        let token = lexer.lex().unwrap();
+        assert_eq!(token.kind(), tok::pub_comment);
+        let (comment, explanation) = lexer.split_explanation(token);
+        assert_eq!(comment.spelling(), "hint #1");
+        let explanation = explanation.unwrap();
+        assert_eq!(explanation.kind(), tok::explanation);
+        assert_eq!(explanation.spelling(), "explanation #! not a hint");
+        assert_eq!(lexer.lex().unwrap().kind(), tok::newline);
+        let token = lexer.lex().unwrap();
+        assert_eq!(token.kind(), tok::explanation);
+        assert_eq!(token.spelling(), "b # c #?d");
+        assert_eq!(lexer.split_explanation(token.clone()), (token, None));
+        assert_eq!(lexer.lex().unwrap().kind(), tok::newline);
+        assert_eq!(lexer.lex().unwrap().kind(), tok::newline);
+        let token = lexer.lex().unwrap();
+        assert_eq!(token.kind(), tok::comment);
+        let (_, explanation) = lexer.split_explanation(token);
+        assert_eq!(explanation.unwrap().span(), 63..67);
+        assert_eq!(lexer.lex().unwrap(), Token::eof());
+    }
+
     #[test]
     fn skip_comments() {
         let source = "#sdf\n#!asdf\n#!\n#\n#";
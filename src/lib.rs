@@ -1,6 +1,11 @@
+pub mod align;
 pub mod args;
+pub mod compare;
 pub mod diag;
 pub mod lexis;
+pub mod progress;
 pub mod quiz;
+pub mod script;
+pub mod settings;
 pub mod source;
 pub mod syntax;
@@ -1,28 +1,61 @@
-use miette::Result;
+use miette::{MietteDiagnostic, Result};
 use std::time::Instant;
 use clap::Parser;
 
 use tort::args::Args;
-use tort::syntax::{self, Line};
+use tort::progress::Progress;
+use tort::script::{Loader, Script};
+use tort::settings::Settings;
+use tort::syntax::{Section, TagFilter};
 use tort::quiz::QuizMachine;
 
 fn main() -> Result<()> {
     let start_time = Instant::now();
     let args = Args::parse();
 
-    let mut lines = Vec::<Line>::new();
-    for path in args.files {
-        let source_name = path.display().to_string();
-        let source = std::fs::read_to_string(path).expect("can't read the input file");
-        let mut parser = syntax::Parser::new(&source_name, &source);
-        lines.append(&mut parser.parse()?);
+    let machine = QuizMachine::new(Settings::from_args(&args), start_time);
+    machine.set_tag_filter(TagFilter::new(&args.tag, &args.exclude_tag));
+    machine.set_interleave(args.interleave);
+    let mut loader = Loader::new();
+    let mut selected_any = false;
+    for (path, weight) in args.weighted_files()? {
+        let script = loader.parse(&path)?;
+        let mut scripts = loader.expand(script)?;
+        if args.list_sections {
+            println!("{}", path.display());
+            print_sections(&Section::new(scripts.into_iter().flat_map(|script| script.lines)).sections);
+            continue;
+        }
+        if !args.section.is_empty() {
+            let Some(selected) = Script::select_sections(scripts, &args.section) else { continue };
+            scripts = selected;
+            selected_any = true;
+        }
+        loader.check_rules(&scripts)?;
+        machine.append(&path.display().to_string(), weight, scripts);
+    }
+    if !args.section.is_empty() && !selected_any && !args.list_sections {
+        let msg = format!("no section matches `{}`", args.section.join("`, `"));
+        return Err(MietteDiagnostic::new(msg).with_severity(miette::Severity::Error).into());
     }
 
-    if !args.check {
-        let machine = QuizMachine::new(args.random, args.number_of_tests.unwrap_or_default(), start_time);
-        machine.append(&mut lines);
+    if !args.check && !args.list_sections {
+        if let Some(path) = args.progress.or_else(Progress::default_path) {
+            machine.set_progress(Progress::open(&path)?);
+        }
+        if args.due {
+            machine.due_only(args.new_per_day)?;
+        }
         machine.run().expect("can't run the quiz");
     }
 
     Ok(())
 }
+
+fn print_sections(sections: &[Section]) {
+    for section in sections {
+        let indent = str::repeat("  ", section.level);
+        println!("{indent}{}", section.title.as_deref().unwrap_or_default());
+        print_sections(&section.sections);
+    }
+}
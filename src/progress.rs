@@ -0,0 +1,305 @@
+use miette::{MietteDiagnostic, Result};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Number of seconds in one day of the scheduler.
+const DAY: u64 = 24 * 60 * 60;
+
+/// First field of the lines of the progress file that keep rule records.
+const RULE_MARK: &str = "@rule";
+
+/// Persistent store of the answering history keyed by a script and a question in it.
+///
+/// It keeps an SM-2 schedule for every question, so that `--due` mode can ask only
+/// the questions whose time has come. Answers of orthograms are also counted by the
+/// rules they refer to.
+pub struct Progress {
+    path: PathBuf,
+    records: HashMap<(String, String), Record>,
+    rules: HashMap<String, RuleRecord>,
+    today: u64,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Record {
+    /// How many times in a row the question was answered right
+    pub repetitions: u32,
+    /// Current interval between repetitions in days
+    pub interval: u32,
+    /// Easiness factor of SM-2 algorithm
+    pub ease: f32,
+    /// The day the question should be asked next time
+    pub due: u64,
+    /// The day the question was asked first time
+    pub introduced: u64,
+}
+
+/// How well a question is answered, it gives the quality of the repetition in SM-2.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Answer {
+    Right,
+    /// Right answer with a few typos, that is a repetition with difficulty
+    Almost,
+    Wrong,
+}
+
+impl Answer {
+    fn quality(self) -> f32 {
+        match self {
+            Answer::Right => 4.,
+            Answer::Almost => 3.,
+            Answer::Wrong => 1.,
+        }
+    }
+}
+
+/// Number of right and all answers of orthograms referring to a rule.
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub struct RuleRecord {
+    pub right: u32,
+    pub done: u32,
+}
+
+impl Record {
+    fn new(today: u64) -> Self {
+        Self {
+            repetitions: 0,
+            interval: 0,
+            ease: 2.5,
+            due: today,
+            introduced: today,
+        }
+    }
+
+    /// Reschedule the record according to SM-2 algorithm. Right answer is treated
+    /// as quality 4, almost right one as quality 3, and wrong one as quality 1.
+    pub fn update(&mut self, answer: Answer, today: u64) {
+        let quality = answer.quality();
+        if answer != Answer::Wrong {
+            self.interval = match self.repetitions {
+                0 => 1,
+                1 => 6,
+                _ => (self.interval as f32 * self.ease).round() as u32,
+            };
+            self.repetitions += 1;
+        } else {
+            self.repetitions = 0;
+            self.interval = 1;
+        }
+        let ease = self.ease + 0.1 - (5. - quality) * (0.08 + (5. - quality) * 0.02);
+        self.ease = ease.max(1.3);
+        self.due = today + self.interval as u64;
+    }
+}
+
+impl Progress {
+    /// Default location of the progress file: `tort/progress.tsv` in the user's data directory.
+    pub fn default_path() -> Option<PathBuf> {
+        dirs_next::data_dir().map(|dir| dir.join("tort").join("progress.tsv"))
+    }
+
+    /// Load the progress file. Nonexistent file is considered as an empty progress.
+    pub fn open(path: &Path) -> Result<Self> {
+        let mut records = HashMap::new();
+        let mut rules = HashMap::new();
+        if path.exists() {
+            let content = std::fs::read_to_string(path)
+                .map_err(|err| aid::io_error("can't read the progress file", path, err))?;
+            for (i, line) in content.lines().enumerate() {
+                if line.is_empty() {
+                    continue;
+                }
+                if let Some((rule, record)) = aid::parse_rule_record(line) {
+                    rules.insert(rule, record);
+                    continue;
+                }
+                let Some((key, record)) = aid::parse_record(line) else {
+                    return Err(MietteDiagnostic::new(format!(
+                        "the progress file `{}` is corrupted at line {}", path.display(), i + 1))
+                        .with_severity(miette::Severity::Error).into());
+                };
+                records.insert(key, record);
+            }
+        }
+        Ok(Self {
+            path: path.to_owned(),
+            records,
+            rules,
+            today: aid::today(),
+        })
+    }
+
+    pub fn today(&self) -> u64 {
+        self.today
+    }
+
+    pub fn get(&self, script: &str, quest: &str) -> Option<&Record> {
+        self.records.get(&(script.to_owned(), quest.to_owned()))
+    }
+
+    /// Whether the question has been asked before and its next repetition day has come.
+    pub fn is_due(&self, script: &str, quest: &str) -> bool {
+        self.get(script, quest).is_some_and(|record| record.due <= self.today)
+    }
+
+    /// Number of questions asked first time today.
+    pub fn introduced_today(&self) -> usize {
+        self.records.values().filter(|record| record.introduced == self.today).count()
+    }
+
+    pub fn record(&mut self, script: &str, quest: &str, answer: Answer) {
+        let today = self.today;
+        self.records
+            .entry((script.to_owned(), quest.to_owned()))
+            .or_insert_with(|| Record::new(today))
+            .update(answer, today);
+    }
+
+    pub fn rule(&self, rule: &str) -> Option<&RuleRecord> {
+        self.rules.get(rule)
+    }
+
+    pub fn record_rule(&mut self, rule: &str, right: bool) {
+        let record = self.rules.entry(rule.to_owned()).or_default();
+        record.right += right as u32;
+        record.done += 1;
+    }
+
+    pub fn save(&self) -> Result<()> {
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir)
+                .map_err(|err| aid::io_error("can't create directory for the progress file", dir, err))?;
+        }
+        let mut records: Vec<_> = self.records.iter().collect();
+        records.sort_by(|a, b| a.0.cmp(b.0));
+        let mut content = String::new();
+        for ((script, quest), record) in records {
+            content += &format!("{}\t{}\t{}\t{}\t{}\t{}\t{}\n",
+                                aid::escape(script), aid::escape(quest), record.repetitions,
+                                record.interval, record.ease, record.due, record.introduced);
+        }
+        let mut rules: Vec<_> = self.rules.iter().collect();
+        rules.sort_by(|a, b| a.0.cmp(b.0));
+        for (rule, record) in rules {
+            content += &format!("{RULE_MARK}\t{}\t{}\t{}\n", aid::escape(rule), record.right, record.done);
+        }
+        std::fs::write(&self.path, content)
+            .map_err(|err| aid::io_error("can't write the progress file", &self.path, err))?;
+        Ok(())
+    }
+}
+
+pub(super) mod aid {
+    use super::*;
+
+    pub fn today() -> u64 {
+        SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_secs() / DAY)
+    }
+
+    pub fn io_error(msg: &str, path: &Path, err: std::io::Error) -> miette::Report {
+        MietteDiagnostic::new(format!("{msg} `{}`: {err}", path.display()))
+            .with_severity(miette::Severity::Error).into()
+    }
+
+    pub fn escape(s: &str) -> String {
+        s.replace('\\', "\\\\").replace('\t', "\\t").replace('\n', "\\n")
+    }
+
+    pub fn unescape(s: &str) -> String {
+        let mut result = String::new();
+        let mut chars = s.chars();
+        while let Some(c) = chars.next() {
+            if c == '\\' {
+                match chars.next() {
+                    Some('t') => result.push('\t'),
+                    Some('n') => result.push('\n'),
+                    Some(c) => result.push(c),
+                    None => result.push('\\'),
+                }
+            } else {
+                result.push(c);
+            }
+        }
+        result
+    }
+
+    pub fn parse_record(line: &str) -> Option<((String, String), Record)> {
+        let fields: Vec<&str> = line.split('\t').collect();
+        if fields.len() != 7 {
+            return None;
+        }
+        let record = Record {
+            repetitions: fields[2].parse().ok()?,
+            interval: fields[3].parse().ok()?,
+            ease: fields[4].parse().ok()?,
+            due: fields[5].parse().ok()?,
+            introduced: fields[6].parse().ok()?,
+        };
+        Some(((unescape(fields[0]), unescape(fields[1])), record))
+    }
+
+    pub fn parse_rule_record(line: &str) -> Option<(String, RuleRecord)> {
+        let fields: Vec<&str> = line.split('\t').collect();
+        if fields.len() != 4 || fields[0] != RULE_MARK {
+            return None;
+        }
+        let record = RuleRecord {
+            right: fields[2].parse().ok()?,
+            done: fields[3].parse().ok()?,
+        };
+        Some((unescape(fields[1]), record))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn sm2_schedule() {
+        let mut record = Record::new(100);
+        record.update(Answer::Right, 100);
+        assert_eq!((record.repetitions, record.interval, record.due), (1, 1, 101));
+        record.update(Answer::Right, 101);
+        assert_eq!((record.repetitions, record.interval, record.due), (2, 6, 107));
+        record.update(Answer::Right, 107);
+        assert_eq!(record.repetitions, 3);
+        assert!(record.interval > 6);
+        record.update(Answer::Wrong, 120);
+        assert_eq!((record.repetitions, record.interval, record.due), (0, 1, 121));
+        assert!(record.ease >= 1.3);
+    }
+
+    #[test]
+    fn almost_right_schedule() {
+        let mut right = Record::new(100);
+        let mut almost = Record::new(100);
+        for today in [100, 101, 107] {
+            right.update(Answer::Right, today);
+            almost.update(Answer::Almost, today);
+        }
+        assert_eq!(almost.repetitions, 3);
+        assert!(almost.ease < right.ease);
+        assert!(almost.interval < right.interval);
+        assert!(almost.due < right.due);
+    }
+
+    #[test]
+    fn record_roundtrip() {
+        let line = format!("{}\t{}\t3\t15\t2.36\t19000\t18980", aid::escape("a\\b.tort"), aid::escape("p[ie]ce\tof"));
+        let ((script, quest), record) = aid::parse_record(&line).unwrap();
+        assert_eq!(script, "a\\b.tort");
+        assert_eq!(quest, "p[ie]ce\tof");
+        assert_eq!(record.interval, 15);
+        assert_eq!(record.due, 19000);
+        assert!(aid::parse_record("a\tb\tc").is_none());
+
+        let (rule, record) = aid::parse_rule_record("@rule\tie\t4\t10").unwrap();
+        assert_eq!(rule, "ie");
+        assert_eq!(record, RuleRecord { right: 4, done: 10 });
+        assert!(aid::parse_rule_record("@rule\tie\t4").is_none());
+        assert!(aid::parse_rule_record(&line).is_none());
+    }
+}
@@ -1,13 +1,20 @@
-use clap::Parser;
 use colored::*;
 use miette::{MietteDiagnostic, Result};
 use rand::{self, seq::SliceRandom};
 use rustyline::error::ReadlineError;
 use rustyline::DefaultEditor;
 use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::ops::Range;
+use std::rc::Rc;
 use std::time::Instant;
 
-use crate::args::Args;
+use crate::align::{self, Filling};
+use crate::args::{AnswerMode, Direction, ExplainMode};
+use crate::compare::{Policies, Policy};
+use crate::progress::{Answer, Progress};
+use crate::script::Script;
+use crate::settings::Settings;
 use crate::syntax::*;
 use crate::lexis::Token;
 
@@ -16,31 +23,246 @@ pub struct QuizMachine {
 }
 
 struct QuizMachineInner {
-    quests: Vec<Line>,
+    quests: Vec<Quest>,
+    units: Vec<Unit>,
+    /// Settings given on the command line
+    settings: Settings,
+    /// Whether questions of the units are mixed instead of asked unit by unit
+    interleave: bool,
     random: bool,
+    answer_mode: AnswerMode,
+    policies: Policies,
+    typos: usize,
+    direction: Direction,
+    retry_wrong: bool,
+    explain: ExplainMode,
+    new_per_day: Option<usize>,
+    tag_filter: TagFilter,
+    /// Rules of the glossaries by their ids
+    rules: HashMap<String, Rule>,
+    progress: Option<Progress>,
     readline: DefaultEditor,
     stats: AnswerStatistic,
     prev_was_comment: bool
 }
 
+/// An input file together with the scripts it includes. Every unit has its own
+/// settings, header and shuffle scope.
+struct Unit {
+    name: String,
+    /// Leading public comments of the file
+    header: Vec<String>,
+    /// Settings of the file merged with the ones from the command line
+    settings: Settings,
+    /// Percentage of the questions limited with `-n` that are taken from the unit
+    weight: Option<usize>,
+    /// Whether the header is already printed
+    entered: bool
+}
+
+/// A line of a script together with the script and the unit it comes from.
+#[derive(Clone)]
+struct Quest {
+    script: Rc<str>,
+    unit: usize,
+    line: Line,
+    /// Whether the line is asked again after a wrong answer
+    retry: bool
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Verdict {
+    Right,
+    /// A few typos are made, but the answer is rather right
+    Almost,
+    Wrong
+}
+
+/// Everything needed to ask a line.
+struct Question<'a> {
+    quest_prompt: &'a str,
+    answer_prompt: &'a str,
+    question: String,
+    /// Canonical right answer
+    right_answer: String,
+    /// Other accepted answers
+    alternatives: Vec<String>,
+    /// Char ranges of orthograms in the right answer
+    orthograms: Vec<Range<usize>>,
+    /// Accepted spellings of every orthogram, the first one is in the right answer
+    variants: Vec<Vec<String>>,
+    /// Numbered variants of every orthogram in select mode (empty for gaps)
+    choices: Vec<Vec<String>>,
+    policy: Policy,
+    /// How many typos make the answer almost right (0 disables such verdict)
+    typos: usize,
+    comment: Option<&'a str>,
+    /// Explanation of the line shown after the answer
+    explanation: Option<&'a str>,
+    /// Explanations of every orthogram shown after the answer
+    orthogram_explanations: Vec<Option<String>>,
+    /// Rules every orthogram refers to
+    rules: Vec<Option<Rule>>,
+    /// Whether the orthograms are stressed words, where only the stress is answered
+    stress: bool,
+    /// Whether the orthograms are words split into segments, and only they are answered
+    segments: bool,
+    /// Options of a choice question in the shown order, with whether they are right
+    options: Vec<(String, bool)>
+}
+
+impl<'a> Question<'a> {
+    fn new(quest_prompt: &'a str, answer_prompt: &'a str, question: String, right_answer: String,
+           policy: Policy, comment: Option<&'a str>) -> Self {
+        Self {
+            quest_prompt,
+            answer_prompt,
+            question,
+            right_answer,
+            alternatives: Vec::new(),
+            orthograms: Vec::new(),
+            variants: Vec::new(),
+            choices: Vec::new(),
+            policy,
+            typos: 0,
+            comment,
+            explanation: None,
+            orthogram_explanations: Vec::new(),
+            rules: Vec::new(),
+            stress: false,
+            segments: false,
+            options: Vec::new()
+        }
+    }
+
+    fn with_alternatives(self, alternatives: Vec<String>) -> Self {
+        Self { alternatives, ..self }
+    }
+
+    fn with_orthograms(self, orthograms: Vec<Range<usize>>, variants: Vec<Vec<String>>) -> Self {
+        Self { orthograms, variants, ..self }
+    }
+
+    fn with_choices(self, choices: Vec<Vec<String>>) -> Self {
+        Self { choices, ..self }
+    }
+
+    fn with_typos(self, typos: usize) -> Self {
+        Self { typos, ..self }
+    }
+
+    fn with_explanations(self, explanation: Option<&'a str>, orthogram_explanations: Vec<Option<String>>) -> Self {
+        Self { explanation, orthogram_explanations, ..self }
+    }
+
+    fn with_rules(self, rules: Vec<Option<Rule>>) -> Self {
+        Self { rules, ..self }
+    }
+
+    fn with_stress(self, stress: bool) -> Self {
+        Self { stress, ..self }
+    }
+
+    fn with_segments(self, segments: bool) -> Self {
+        Self { segments, ..self }
+    }
+
+    fn with_options(self, options: Vec<(String, bool)>) -> Self {
+        Self { options, ..self }
+    }
+
+    fn has_explanations(&self) -> bool {
+        self.explanation.is_some() || self.orthogram_explanations.iter().any(Option::is_some) ||
+            self.rules.iter().any(Option::is_some)
+    }
+}
+
+/// Result of checking the user's answer.
+struct Check {
+    verdict: Verdict,
+    /// Fillings of the orthograms of the question
+    fillings: Vec<Filling>,
+    /// Ids of the rules the orthograms refer to
+    rules: Vec<Option<String>>,
+    /// Whether every option of a choice question is chosen or left right
+    options: Vec<bool>
+}
+
+/// Numbers of right, almost right and all answers of a group of tests.
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+struct Tally {
+    right: usize,
+    almost: usize,
+    done: usize
+}
+
+impl Tally {
+    fn add(&mut self, verdict: Verdict) {
+        match verdict {
+            Verdict::Right => self.right += 1,
+            Verdict::Almost => self.almost += 1,
+            Verdict::Wrong => ()
+        }
+        self.done += 1;
+    }
+
+    /// Spelling like `3 from 4 (75.0%), 1 almost right`.
+    fn spell(&self) -> String {
+        let mut spelling = format!("{} from {}", format!("{}", self.right).bold(), format!("{}", self.done).bold());
+        if self.done > 0 {
+            spelling += &format!(" ({}%)", format!("{:.1}", self.right as f32 / self.done as f32 * 100.).bold());
+        }
+        if self.almost > 0 {
+            spelling += &format!(", {} almost right", format!("{}", self.almost).bold());
+        }
+        spelling
+    }
+}
+
 #[derive(Clone)]
 struct AnswerStatistic {
     right_answers: usize,
+    almost_answers: usize,
     wrong_answers: usize,
+    fixed_answers: usize,
+    retried_answers: usize,
+    right_orthograms: usize,
+    wrong_orthograms: usize,
+    right_options: usize,
+    wrong_options: usize,
+    /// Answers of tests per tag
+    tags: BTreeMap<String, Tally>,
+    /// Answers of orthograms per rule
+    rules: BTreeMap<String, Tally>,
+    /// Names of units with answers of their tests
+    units: Vec<(String, Tally)>,
     to_run_tests: usize,
     all_tests: usize,
     done_tests: usize,
+    /// Whether only the tests due to be repeated are asked
+    due: bool,
     start_time: Instant
 }
 
 impl AnswerStatistic {
-    pub fn new(number_of_tests: usize, start_time: Instant) -> Self {
+    pub fn new(start_time: Instant) -> Self {
         Self {
             right_answers: 0,
+            almost_answers: 0,
             wrong_answers: 0,
-            to_run_tests: number_of_tests,
+            fixed_answers: 0,
+            retried_answers: 0,
+            right_orthograms: 0,
+            wrong_orthograms: 0,
+            right_options: 0,
+            wrong_options: 0,
+            tags: BTreeMap::new(),
+            rules: BTreeMap::new(),
+            units: Vec::new(),
+            to_run_tests: 0,
             all_tests: 0,
             done_tests: 0,
+            due: false,
             start_time
         }
     }
@@ -51,8 +273,13 @@ impl AnswerStatistic {
         println!("{}\n", str::repeat("=", 80).blue());
     }
 
-    pub fn print_footnote(&self) {
+    pub fn print_footnote(&self, rules: &HashMap<String, Rule>, progress: Option<&Progress>) {
         println!("{}", str::repeat("=", 80).blue());
+        if let Some(note) = self.empty_note() {
+            println!("{note}");
+            println!("{}", str::repeat("=", 80).blue());
+            return;
+        }
         println!("Done {} tests from {}", format!("{}", self.done_tests).bold(), format!("{}", self.all_tests).bold());
         println!("Elapsed time: {}\n", format!("{:?}", self.start_time.elapsed()).bold());
         let right_answers = format!("{}", self.right_answers).bold();
@@ -60,95 +287,268 @@ impl AnswerStatistic {
         let right_percent = format!("{:.1}", self.right_answers as f32 / self.done_tests as f32 * 100.).bold();
         let wrong_percent = format!("{:.1}", self.wrong_answers as f32 / self.done_tests as f32 * 100.).bold();
         println!("{} {} ({}%)", "Right answers:".green(), right_answers, right_percent);
+        if self.almost_answers > 0 {
+            let almost_percent = format!("{:.1}", self.almost_answers as f32 / self.done_tests as f32 * 100.).bold();
+            println!("{} {} ({}%)", "Almost right answers:".yellow(), format!("{}", self.almost_answers).bold(),
+                     almost_percent);
+        }
         println!("{} {} ({}%)", "Wrong answers:".red(), wrong_answers, wrong_percent);
+        let all_orthograms = self.right_orthograms + self.wrong_orthograms;
+        if all_orthograms > 0 {
+            let right_percent = format!("{:.1}", self.right_orthograms as f32 / all_orthograms as f32 * 100.).bold();
+            println!("{} {} from {} ({}%)", "Right orthograms:".green(), format!("{}", self.right_orthograms).bold(),
+                     format!("{}", all_orthograms).bold(), right_percent);
+        }
+        let all_options = self.right_options + self.wrong_options;
+        if all_options > 0 {
+            let right_percent = format!("{:.1}", self.right_options as f32 / all_options as f32 * 100.).bold();
+            println!("{} {} from {} ({}%)", "Right options:".green(), format!("{}", self.right_options).bold(),
+                     format!("{}", all_options).bold(), right_percent);
+        }
+        if self.retried_answers > 0 {
+            let eventual_answers = self.right_answers + self.almost_answers + self.fixed_answers;
+            let eventual_percent = format!("{:.1}", eventual_answers as f32 / self.done_tests as f32 * 100.).bold();
+            println!("{} {} ({}%) after {} retries", "Eventually right:".green(),
+                     format!("{}", eventual_answers).bold(), eventual_percent,
+                     format!("{}", self.retried_answers).bold());
+        }
+        if !self.tags.is_empty() {
+            println!("{}", "Right answers by tags:".green());
+            for (tag, tally) in &self.tags {
+                println!("  @{tag}: {}", tally.spell());
+            }
+        }
+        if !self.rules.is_empty() {
+            println!("{}", "Right orthograms by rules:".green());
+            for (id, tally) in &self.rules {
+                let title = rules.get(id).map(|rule| rule.title.as_str()).unwrap_or_default();
+                print!("  @{id} {title}: {}", tally.spell());
+                match progress.and_then(|progress| progress.rule(id)) {
+                    Some(record) if record.done > 0 => {
+                        let overall_percent = format!("{:.1}", record.right as f32 / record.done as f32 * 100.);
+                        println!(", {}% of {} overall", overall_percent.bold(), format!("{}", record.done).bold());
+                    },
+                    _ => println!()
+                }
+            }
+        }
+        if self.units.len() > 1 {
+            println!("{}", "Right answers by files:".green());
+            for (name, tally) in &self.units {
+                println!("  {name}: {}", tally.spell());
+            }
+        }
         println!("{}", str::repeat("=", 80).blue());
     }
+
+    /// The note printed instead of the statistics when no tests are done.
+    fn empty_note(&self) -> Option<&'static str> {
+        match (self.done_tests, self.due) {
+            (0, true) => Some("Nothing is due today"),
+            (0, false) => Some("No tests are done"),
+            _ => None
+        }
+    }
+
+    /// Count the check. Only first tries count as done tests and orthograms, and
+    /// retries count to the eventual accuracy.
+    fn count(&mut self, check: &Check, quest: &Quest) {
+        let retry = quest.retry;
+        match (check.verdict, retry) {
+            (Verdict::Right, false) => self.right_answers += 1,
+            (Verdict::Almost, false) => self.almost_answers += 1,
+            (Verdict::Wrong, false) => self.wrong_answers += 1,
+            (Verdict::Right | Verdict::Almost, true) => self.fixed_answers += 1,
+            (Verdict::Wrong, true) => ()
+        }
+        if retry {
+            self.retried_answers += 1;
+        } else {
+            self.done_tests += 1;
+            let right_orthograms = check.fillings.iter().filter(|filling| filling.right).count();
+            self.right_orthograms += right_orthograms;
+            self.wrong_orthograms += check.fillings.len() - right_orthograms;
+            let right_options = check.options.iter().filter(|right| **right).count();
+            self.right_options += right_options;
+            self.wrong_options += check.options.len() - right_options;
+            for tag in quest.line.tags() {
+                self.tags.entry(tag.clone()).or_default().add(check.verdict);
+            }
+            if let Some((_, tally)) = self.units.get_mut(quest.unit) {
+                tally.add(check.verdict);
+            }
+            for (rule, filling) in check.rules.iter().zip(&check.fillings) {
+                if let Some(rule) = rule {
+                    let verdict = if filling.right { check.verdict } else { Verdict::Wrong };
+                    self.rules.entry(rule.clone()).or_default().add(verdict);
+                }
+            }
+        }
+    }
 }
 
 impl QuizMachine {
-    pub fn new(random: bool, number_of_tests: usize, start_time: Instant) -> Self {
+    /// Create a quiz machine with the settings given on the command line. They take
+    /// precedence over `#!set` directives of the scripts.
+    pub fn new(settings: Settings, start_time: Instant) -> Self {
         Self {
             inner: RefCell::new(QuizMachineInner {
                 quests: Vec::new(),
-                random,
+                units: Vec::new(),
+                settings,
+                interleave: false,
+                random: false,
+                answer_mode: AnswerMode::Line,
+                policies: Policies::default(),
+                typos: 0,
+                direction: Direction::Forward,
+                retry_wrong: false,
+                explain: ExplainMode::Wrong,
+                new_per_day: None,
+                tag_filter: TagFilter::default(),
+                rules: HashMap::new(),
+                progress: None,
                 readline: DefaultEditor::new().unwrap(),
-                stats: AnswerStatistic::new(number_of_tests, start_time),
+                stats: AnswerStatistic::new(start_time),
                 prev_was_comment: false
             })
         }
     }
 
-    pub fn append(&self, lines: &mut Vec<Line>) {
-        self.inner.borrow_mut().quests.append(lines);
+    /// Append the input file `name` with the scripts it includes to the quiz as a
+    /// separate unit. Keys of the scripts are used in the progress store. The weight
+    /// is a percentage of the limited number of questions taken from the unit.
+    pub fn append(&self, name: &str, weight: Option<usize>, scripts: Vec<Script>) {
+        let mut inner = self.inner.borrow_mut();
+        let unit = inner.units.len();
+        inner.units.push(Unit { name: name.into(), header: Vec::new(), settings: Settings::default(), weight,
+                                entered: false });
+        for script in scripts {
+            for line in &script.lines {
+                if let Line::Rule(rule) = line {
+                    inner.rules.insert(rule.id.clone(), rule.clone());
+                }
+            }
+            let key: Rc<str> = script.key.into();
+            let quests = script.lines.into_iter().map(|line| Quest { script: key.clone(), unit, line, retry: false });
+            inner.quests.extend(quests);
+        }
+    }
+
+    /// Mix questions of the units instead of asking them unit by unit.
+    pub fn set_interleave(&self, interleave: bool) {
+        self.inner.borrow_mut().interleave = interleave;
+    }
+
+    /// Ask only statements selected by the tag filter.
+    pub fn set_tag_filter(&self, tag_filter: TagFilter) {
+        self.inner.borrow_mut().tag_filter = tag_filter;
+    }
+
+    /// Set the store where the answers are recorded to.
+    pub fn set_progress(&self, progress: Progress) {
+        self.inner.borrow_mut().progress = Some(progress);
+    }
+
+    /// Ask only the tests that are due to be repeated according to the progress store,
+    /// and not more than `new_per_day` new tests a day.
+    pub fn due_only(&self, new_per_day: usize) -> Result<()> {
+        let mut inner = self.inner.borrow_mut();
+        if inner.progress.is_none() {
+            return Err(MietteDiagnostic::new("can't find a place for the progress file, specify it with `--progress`")
+                .with_severity(miette::Severity::Error).into());
+        }
+        inner.new_per_day = Some(new_per_day);
+        Ok(())
     }
     
-    fn pre_run(&self) -> Vec<Line> {
-        let mut first_line = 0;
+    fn pre_run(&self) -> Vec<Quest> {
         let mut inner = self.inner.borrow_mut();
-        let mut new_stats = inner.stats.clone();
-        let mut new_random = inner.random;
-        let mut is_first_pub_comment = true;
-        let mut is_first_line_of_first_pub_comment = true;
-        for line in &inner.quests {
-            match line {
-                Line::PubComment(token) => {
-                    if token.span().start == 0 {
-                        first_line += 1;
-                        continue;
-                    }
-                    let prev_random = inner.random;
-                    const ARGS_PATTERN: &'static str = "ARGS:";
-                    if !prev_random && token.spelling().starts_with(ARGS_PATTERN) {
-                        let arg_line = token.spelling()[ARGS_PATTERN.len()..].trim();
-                        let args = Args::parse_from(arg_line.split_whitespace());
-                        new_random = args.random;
-                        first_line += 1;
-                        continue;
-                    }
-                    if is_first_pub_comment {
-                        if is_first_line_of_first_pub_comment {
-                            println!("{}", str::repeat("=", 80).blue());
-                            is_first_line_of_first_pub_comment = false;
-                        }
-                        println!("{}", token.spelling().blue());
-                        first_line += 1;
+        let inner = &mut *inner;
+        let mut unit_quests: Vec<Vec<Quest>> = inner.units.iter().map(|_| Vec::new()).collect();
+        for quest in inner.quests.drain(..) {
+            unit_quests[quest.unit].push(quest);
+        }
+        let mut new_quota = match (inner.new_per_day, &inner.progress) {
+            (Some(new_per_day), Some(progress)) => Some(new_per_day.saturating_sub(progress.introduced_today())),
+            _ => None
+        };
+        inner.stats.all_tests = 0;
+        inner.stats.due = new_quota.is_some();
+        for (unit, quests) in inner.units.iter_mut().zip(unit_quests.iter_mut()) {
+            let (header, file_settings, mut lines) = aid::split_header(std::mem::take(quests));
+            // the limit of the whole run given on the command line overrides limits of scripts
+            let tests = file_settings.tests.filter(|_| inner.settings.tests.is_none());
+            unit.header = header;
+            // settings given on the command line take precedence over ones from scripts
+            unit.settings = inner.settings.clone().or(file_settings);
+            if !inner.tag_filter.is_empty() {
+                lines.retain(|quest| !quest.line.is_stmt() || inner.tag_filter.matches(quest.line.tags()));
+            }
+            if let (Some(new_quota), Some(progress)) = (&mut new_quota, &inner.progress) {
+                lines.retain(|quest| {
+                    let Some(key) = aid::quest_key(&quest.line) else { return true };
+                    if progress.get(&quest.script, &key).is_some() {
+                        progress.is_due(&quest.script, &key)
+                    } else if *new_quota > 0 {
+                        *new_quota -= 1;
+                        true
+                    } else {
+                        false
                     }
-                },
-                Line::ComplexStmt { text: _, comment: _ } => {
-                    new_stats.all_tests += 1;
-                    is_first_pub_comment = false;
-                }
-                Line::PlainStmt { text: _, comment: _ } => {
-                    new_stats.all_tests += 1;
-                    is_first_pub_comment = false;
-                },
-                Line::TranslationStmt { original: _, translation: _, comment: _ } => {
-                    new_stats.all_tests += 1;
-                    is_first_pub_comment = false;
-                },
-                Line::Empty => {
-                    is_first_pub_comment = false;
+                });
+            }
+            inner.stats.all_tests += lines.iter().filter(|quest| quest.line.is_stmt()).count();
+            if unit.settings.random == Some(true) {
+                let mut rng = rand::thread_rng();
+                for section in lines.split_mut(|quest| matches!(quest.line, Line::Heading { .. })) {
+                    section.shuffle(&mut rng);
                 }
             }
+            if let Some(tests) = tests.filter(|tests| *tests > 0) {
+                aid::take_tests(&mut lines, tests);
+            }
+            *quests = lines;
         }
-        inner.stats = new_stats;
-        let mut lines: Vec<Line> = inner.quests[first_line..].into();
-        if new_random {
-            let mut rng = rand::thread_rng();
-            lines.shuffle(&mut rng);
-            inner.random = new_random;
+        inner.stats.units = inner.units.iter().map(|unit| (unit.name.clone(), Tally::default())).collect();
+        if let Some(limit) = inner.settings.tests.filter(|limit| *limit > 0) {
+            if inner.units.iter().any(|unit| unit.weight.is_some()) {
+                let weights: Vec<Option<usize>> = inner.units.iter().map(|unit| unit.weight).collect();
+                let available: Vec<usize> = unit_quests.iter()
+                    .map(|lines| lines.iter().filter(|quest| quest.line.is_stmt()).count())
+                    .collect();
+                for (lines, quota) in unit_quests.iter_mut().zip(aid::quotas(limit, &weights, &available)) {
+                    aid::sample_tests(lines, quota);
+                }
+            }
         }
+
+        let lines = if inner.interleave {
+            aid::interleave(unit_quests)
+        } else {
+            unit_quests.concat()
+        };
+        let tests = lines.iter().filter(|quest| quest.line.is_stmt()).count();
+        inner.stats.to_run_tests = match inner.settings.tests {
+            Some(limit) if limit > 0 => std::cmp::min(limit, tests),
+            _ => tests
+        };
         lines
     }
 
     pub fn run(&self) -> Result<()> {
-        let lines = self.pre_run();
+        let mut quests: VecDeque<Quest> = self.pre_run().into();
         let mut inner = self.inner.borrow_mut();
         
         inner.stats.print_headnote();
-        for line in lines {
-            match line {
-                Line::Empty => continue,
+        let mut unit = None;
+        while let Some(quest) = quests.pop_front() {
+            if unit != Some(quest.unit) {
+                unit = Some(quest.unit);
+                inner.enter_unit(quest.unit);
+            }
+            let check = match &quest.line {
+                Line::Set(_) | Line::Rule(_) | Line::Include { .. } | Line::Empty => continue,
                 Line::PubComment(token) => {
                     if !inner.random {
                         let spelling = token.spelling();
@@ -156,78 +556,349 @@ impl QuizMachine {
                     }
                     continue;
                 },
-                Line::PlainStmt { text, comment } => {
-                    let original = aid::spell_text(&text);
+                Line::Heading { level, title } => {
+                    inner.print_heading(*level, title);
+                    continue;
+                },
+                Line::PlainStmt { text, comment, explanation, .. } => {
+                    let original = aid::spell_text(text);
                     let comment = comment.as_ref().map(|c| c.spelling());
-                    if inner.ask("Repeat", "Type", &original, &original, comment)? {
-                        break;
-                    }
+                    let explanation = aid::explanation(explanation);
+                    let policy = inner.policies.plain;
+                    let question = Question::new("Repeat", "Type", original.clone(), original, policy, comment)
+                        .with_explanations(explanation, Vec::new());
+                    inner.ask(&question)?
                 },
-                Line::ComplexStmt { text, comment } => {
-                    let question = text.spell_question().yellow();
-                    let right_answer = text.spell_answer();
+                Line::StressStmt { text, comment, explanation, .. } => {
+                    let right_answer = aid::spell_text(text);
+                    let (question, orthograms) = aid::spell_stress_question(text);
+                    let chars: Vec<char> = right_answer.chars().collect();
+                    let variants = orthograms.iter().map(|range| vec![chars[range.clone()].iter().collect()]).collect();
                     let comment = comment.as_ref().map(|c| c.spelling());
-                    if inner.ask("Fill gaps", "Your answer", &question, &right_answer, comment)? {
-                        break;
-                    }
+                    let policy = Policy { ignore_marks: false, ..inner.policies.plain };
+                    let question = Question::new("Stress", "Your answer", question, right_answer, policy, comment)
+                        .with_orthograms(orthograms, variants)
+                        .with_stress(true)
+                        .with_explanations(aid::explanation(explanation), Vec::new());
+                    inner.ask(&question)?
                 },
-                Line::TranslationStmt { original, translation, comment } => {
-                    let original = aid::spell_text(&original);
-                    let translation = aid::spell_text(&translation);
+                Line::SegmentStmt { text, comment, explanation, .. } => {
+                    let (question, right_answer, orthograms) = aid::spell_segments(text);
+                    let chars: Vec<char> = right_answer.chars().collect();
+                    let variants = orthograms.iter().map(|range| vec![chars[range.clone()].iter().collect()]).collect();
                     let comment = comment.as_ref().map(|c| c.spelling());
-                    if inner.ask("Translate", "Your answer", &original, &translation, comment)? {
-                        break;
-                    }
+                    let policy = Policy { ignore_punct: false, ..inner.policies.plain };
+                    let question = Question::new("Split", "Your answer", question, right_answer, policy, comment)
+                        .with_orthograms(orthograms, variants)
+                        .with_segments(true)
+                        .with_explanations(aid::explanation(explanation), Vec::new());
+                    inner.ask(&question)?
+                },
+                Line::ChoiceStmt { question, right_options, wrong_options, comment, explanation, .. } => {
+                    let mut options: Vec<(String, bool)> = right_options.iter()
+                        .map(|option| (aid::spell_text(option), true))
+                        .chain(wrong_options.iter().map(|option| (aid::spell_text(option), false)))
+                        .collect();
+                    options.shuffle(&mut rand::thread_rng());
+                    let right_answer = aid::option_numbers(options.iter().map(|(_, right)| *right));
+                    let comment = comment.as_ref().map(|c| c.spelling());
+                    let question = Question::new("Choose", "Your answer", aid::spell_text(question), right_answer,
+                                                 Policy::strict(), comment)
+                        .with_options(options)
+                        .with_explanations(aid::explanation(explanation), Vec::new());
+                    inner.ask(&question)?
+                },
+                Line::ComplexStmt { text, comment, explanation, .. } => {
+                    let comment = comment.as_ref().map(|c| c.spelling());
+                    let question = inner.orthogram_question("Fill gaps", text, inner.policies.complex, comment,
+                                                            aid::explanation(explanation));
+                    inner.ask(&question)?
+                },
+                Line::TranslationStmt { original, translations, bidirectional, comment, explanation, .. } => {
+                    let original = aid::spell_text(original);
+                    let mut translations: Vec<String> = translations.iter().map(aid::spell_text).collect();
+                    let translation = translations.remove(0);
+                    let (original, translation, alternatives) = if inner.is_reversed(*bidirectional) {
+                        (translation, original, Vec::new())
+                    } else {
+                        (original, translation, translations)
+                    };
+                    let comment = comment.as_ref().map(|c| c.spelling());
+                    let question = Question::new("Translate", "Your answer", original, translation,
+                                                 inner.policies.translation, comment)
+                        .with_alternatives(alternatives)
+                        .with_typos(inner.typos)
+                        .with_explanations(aid::explanation(explanation), Vec::new());
+                    inner.ask(&question)?
+                },
+                Line::ComplexTranslationStmt { original, translation, bidirectional, comment, explanation, .. } => {
+                    let (original, translation) = if inner.is_reversed(*bidirectional) {
+                        (translation, original)
+                    } else {
+                        (original, translation)
+                    };
+                    let comment = comment.as_ref().map(|c| c.spelling());
+                    let mut question = inner.orthogram_question("Translate", translation, inner.policies.translation,
+                                                                comment, aid::explanation(explanation));
+                    // orthograms of the translation are shown as a hint after the original
+                    let original = original.spell_answer();
+                    question.question = if question.orthograms.is_empty() {
+                        original
+                    } else {
+                        format!("{original} {} {}", "->".blue(), question.question)
+                    };
+                    inner.ask(&question)?
                 }
+            };
+            let Some(check) = check else { break };
+            inner.stats.count(&check, &quest);
+            if !quest.retry {
+                inner.record(&quest, &check);
+            }
+            if check.verdict == Verdict::Wrong && inner.retry_wrong {
+                let position = aid::retry_position(&quests);
+                quests.insert(position, Quest { retry: true, ..quest });
             }
             if inner.stats.to_run_tests == inner.stats.done_tests {
-                break;
-            } else {
+                quests.retain(|quest| quest.retry);
             }
         }
-        inner.stats.print_footnote();
+        inner.stats.print_footnote(&inner.rules, inner.progress.as_ref());
+        if let Some(progress) = &inner.progress {
+            progress.save()?;
+        }
         Ok(())
     }
 }
 
 impl QuizMachineInner {
+    /// Apply settings of the unit, and print its header when the unit is entered
+    /// for the first time.
+    fn enter_unit(&mut self, unit: usize) {
+        let settings = &self.units[unit].settings;
+        self.random = settings.random.unwrap_or_default();
+        self.answer_mode = settings.answer.unwrap_or(AnswerMode::Line);
+        self.policies = Policies::default();
+        self.policies.update(settings);
+        self.typos = settings.typos.unwrap_or_default();
+        self.direction = settings.direction.unwrap_or(Direction::Forward);
+        self.retry_wrong = settings.retry_wrong.unwrap_or_default();
+        self.explain = settings.explain.unwrap_or(ExplainMode::Wrong);
+        if self.units[unit].entered {
+            return;
+        }
+        self.units[unit].entered = true;
+        if self.units.len() > 1 {
+            println!("{}\n", self.units[unit].name.bold().blue());
+        }
+        for comment in self.units[unit].header.clone() {
+            self.print_comment(&comment);
+        }
+    }
+
+    /// Whether a translation line is asked from translation to original this time.
+    fn is_reversed(&self, bidirectional: bool) -> bool {
+        bidirectional && match self.direction {
+            Direction::Forward => false,
+            Direction::Reverse => true,
+            Direction::Both => rand::random()
+        }
+    }
+
+    /// Question to fill orthograms of the text according to the answer mode.
+    fn orthogram_question<'a>(&self, quest_prompt: &'a str, text: &Vec<Lexeme>, policy: Policy,
+                              comment: Option<&'a str>, explanation: Option<&'a str>) -> Question<'a> {
+        let (question, choices) = if self.answer_mode == AnswerMode::Select {
+            aid::spell_numbered_question(text)
+        } else {
+            (text.spell_question(), Vec::new())
+        };
+        let (right_answer, orthograms) = aid::spell_answer_with_ranges(text);
+        let rules = aid::orthogram_rules(text).iter()
+            .map(|rule| rule.as_ref().and_then(|id| self.rules.get(id)).cloned())
+            .collect();
+        Question::new(quest_prompt, "Your answer", question.yellow().to_string(), right_answer, policy, comment)
+            .with_orthograms(orthograms, aid::orthogram_variants(text))
+            .with_choices(choices)
+            .with_explanations(explanation, aid::orthogram_explanations(text))
+            .with_rules(rules)
+    }
+
     fn print_comment(&mut self, comment: &str) {
         println!(" {}", comment.blue());
         self.prev_was_comment = true;
     }
+
+    fn print_heading(&mut self, level: usize, title: &str) {
+        println!("{} {}\n", str::repeat("#", level + 1).blue(), title.bold().blue());
+        self.prev_was_comment = false;
+    }
     
-    fn ask(&mut self, quest_prompt: &str, answer_prompt: &str, question: &str, right_answer: &str, comment: Option<&str>)
-        -> Result<bool>
-    {
+    fn record(&mut self, quest: &Quest, check: &Check) {
+        let Some(progress) = &mut self.progress else { return };
+        if let Some(key) = aid::quest_key(&quest.line) {
+            let answer = match check.verdict {
+                Verdict::Right => Answer::Right,
+                Verdict::Almost => Answer::Almost,
+                Verdict::Wrong => Answer::Wrong
+            };
+            progress.record(&quest.script, &key, answer);
+        }
+        for (rule, filling) in check.rules.iter().zip(&check.fillings) {
+            if let Some(rule) = rule {
+                progress.record_rule(rule, filling.right);
+            }
+        }
+    }
+
+    /// Ask the question and check the answer. Returns `None` if the user has interrupted the quiz.
+    fn ask(&mut self, quest: &Question) -> Result<Option<Check>> {
         if self.prev_was_comment {
             println!("{}\n", str::repeat("_", 80).blue());
         }
-        let prompt_width = std::cmp::max(quest_prompt.len(), answer_prompt.len());
-        let prompt_width = std::cmp::max(prompt_width, " ---> ".len()) + 1;
-        let quest_prompt = format!("{quest_prompt}:").bold();
-        let answer_prompt = format!("{answer_prompt}:").bold();
+        let prompt_width = std::cmp::max(quest.quest_prompt.len(), quest.answer_prompt.len());
+        let label_width = if quest.has_explanations() {
+            "Explanation".len()
+        } else if quest.orthograms.is_empty() {
+            " ---> ".len()
+        } else {
+            "Orthograms".len()
+        };
+        let prompt_width = std::cmp::max(prompt_width, label_width) + 1;
+        let quest_prompt = format!("{}:", quest.quest_prompt).bold();
+        let answer_prompt = format!("{}:", quest.answer_prompt).bold();
 
-        print!("{quest_prompt:>prompt_width$}  {question}");
-        if let Some(comment) = comment {
+        print!("{quest_prompt:>prompt_width$}  {}", quest.question);
+        if let Some(comment) = quest.comment {
             print!("   {}", format!("({comment})").blue());
         }
         println!();
+        for (i, (option, _)) in quest.options.iter().enumerate() {
+            println!("{:>prompt_width$}  {}", format!("{}:", i + 1).blue(), option);
+        }
 
-        let answer_prompt = format!("{answer_prompt:>prompt_width$}  ");
-        let Some(answer) = self.readline(&answer_prompt)? else { return Ok(true) };
-        if answer != right_answer {
-            println!("{:>prompt_width$}  {}", "---> ".bold(), "Wrong".red().bold());
+        let mut chosen = Vec::new();
+        let mut words = Vec::new();
+        let answer = if !quest.options.is_empty() {
+            let answer_prompt = format!("{answer_prompt:>prompt_width$}  ");
+            let Some(selection) = self.readline(&answer_prompt)? else { return Ok(None) };
+            let (answer, numbers) = aid::select_options(&selection, quest.options.len());
+            chosen = numbers;
+            answer
+        } else if self.answer_mode == AnswerMode::Select && !quest.choices.is_empty() {
+            let answer_prompt = format!("{answer_prompt:>prompt_width$}  ");
+            let Some(answer) = self.readline(&answer_prompt)? else { return Ok(None) };
+            let fillings = aid::select_fillings(&answer, &quest.choices);
+            aid::fill_orthograms(&quest.right_answer, &quest.orthograms, &fillings).0
+        } else if quest.segments {
+            for i in 0..quest.orthograms.len() {
+                let word_prompt = if quest.orthograms.len() == 1 { answer_prompt.clone() } else {
+                    format!("Word {}:", i + 1).bold()
+                };
+                let Some(word) = self.readline(&format!("{word_prompt:>prompt_width$}  "))? else { return Ok(None) };
+                words.push(word.trim().to_string());
+            }
+            aid::fill_orthograms(&quest.right_answer, &quest.orthograms, &words).0
+        } else if self.answer_mode == AnswerMode::Gaps && !quest.orthograms.is_empty() {
+            let mut fillings = Vec::new();
+            for i in 0..quest.orthograms.len() {
+                let gap_prompt = format!("Gap {}:", i + 1).bold();
+                let Some(filling) = self.readline(&format!("{gap_prompt:>prompt_width$}  "))? else { return Ok(None) };
+                fillings.push(filling);
+            }
+            if quest.stress {
+                fillings = aid::stressed_fillings(quest, &fillings);
+            }
+            aid::fill_orthograms(&quest.right_answer, &quest.orthograms, &fillings).0
+        } else {
+            let answer_prompt = format!("{answer_prompt:>prompt_width$}  ");
+            let Some(answer) = self.readline(&answer_prompt)? else { return Ok(None) };
+            if quest.stress { aid::stressed_answer(&answer, quest) } else { answer }
+        };
+        let (right_answer, orthograms) = aid::closest_answer(&answer, quest);
+        let right_answer = right_answer.as_str();
+        let mut fillings = align::grade(&answer, right_answer, &orthograms);
+        let verdict = if quest.policy.matches(&answer, right_answer) {
+            println!("{:>prompt_width$}  {}", "---> ".bold(), "Right".green().bold());
+            if answer != right_answer {
+                // the difference is ignored by the comparison policy, but it's worth showing
+                let diff = prettydiff::diff_chars(&answer, right_answer);
+                println!("{:>prompt_width$}  {}", "Right:".bold(), diff);
+                fillings.iter_mut().for_each(|filling| filling.right = true);
+            }
+            Verdict::Right
+        } else if quest.typos > 0 && quest.policy.distance(&answer, right_answer) <= quest.typos {
+            println!("{:>prompt_width$}  {}", "---> ".bold(), "Almost \u{2014} check the spelling".yellow().bold());
             let diff = prettydiff::diff_chars(&answer, right_answer);
             println!("{:>prompt_width$}  {}", "Right:".bold(), diff);
-            self.stats.wrong_answers += 1;
+            Verdict::Almost
         } else {
-            println!("{:>prompt_width$}  {}", "---> ".bold(), "Right".green().bold());
-            self.stats.right_answers += 1;
+            println!("{:>prompt_width$}  {}", "---> ".bold(), "Wrong".red().bold());
+            let diff = prettydiff::diff_chars(&answer, right_answer);
+            println!("{:>prompt_width$}  {}", "Right:".bold(), diff);
+            if !fillings.is_empty() && !quest.segments {
+                let checked = aid::spell_checked(right_answer, &orthograms, &fillings, &quest.variants);
+                println!("{:>prompt_width$}  {}", "Orthograms:".bold(), checked);
+            }
+            let boundaries: Vec<(String, String)> = words.iter().zip(&quest.variants)
+                .filter_map(|(word, variants)| aid::boundaries(word, &variants[0]))
+                .collect();
+            let missed: Vec<&str> = boundaries.iter().map(|(missed, _)| missed.as_str())
+                .filter(|word| !word.is_empty())
+                .collect();
+            let extra: Vec<&str> = boundaries.iter().map(|(_, extra)| extra.as_str())
+                .filter(|word| !word.is_empty())
+                .collect();
+            if !missed.is_empty() {
+                println!("{:>prompt_width$}  {}", "Missed:".bold(), missed.join(", ").green().bold());
+            }
+            if !extra.is_empty() {
+                println!("{:>prompt_width$}  {}", "Extra:".bold(), extra.join(", ").red().bold());
+            }
+            let right_options: Vec<&str> = quest.options.iter()
+                .filter(|(_, right)| *right)
+                .map(|(option, _)| option.as_str())
+                .collect();
+            if !right_options.is_empty() {
+                println!("{:>prompt_width$}  {}", "Options:".bold(), right_options.join(" | ").green().bold());
+            }
+            Verdict::Wrong
+        };
+        if !quest.alternatives.is_empty() {
+            let mut answers = vec![quest.right_answer.bold().to_string()];
+            answers.extend(quest.alternatives.iter().cloned());
+            println!("{:>prompt_width$}  {}", "Answers:".bold(), answers.join(" | "));
+        }
+        let always = self.explain == ExplainMode::Always;
+        let mut explanations = Vec::new();
+        if let Some(explanation) = quest.explanation.filter(|_| always || verdict == Verdict::Wrong) {
+            explanations.push(explanation.blue().to_string());
+        }
+        let orthograms = quest.orthogram_explanations.iter().zip(&quest.variants).zip(&fillings);
+        for ((explanation, variants), filling) in orthograms {
+            if let Some(explanation) = explanation.as_ref().filter(|_| always || !filling.right) {
+                explanations.push(format!("{} {}", format!("{}:", variants[0]).bold(), explanation.blue()));
+            }
+        }
+        for (i, explanation) in explanations.iter().enumerate() {
+            let label = if i == 0 { "Explanation:" } else { "" };
+            println!("{:>prompt_width$}  {}", label.bold(), explanation);
+        }
+        for (rule, filling) in quest.rules.iter().zip(&fillings) {
+            if let Some(rule) = rule.as_ref().filter(|_| always || !filling.right) {
+                println!("{:>prompt_width$}  {} {}", "Rule:".bold(), format!("@{}", rule.id).bold(), rule.title);
+                if let Some(explanation) = &rule.explanation {
+                    println!("{:>prompt_width$}  {}", "", explanation.blue());
+                }
+            }
         }
-        self.stats.done_tests += 1;
         self.prev_was_comment = false;
         println!("{}\n", str::repeat("_", 80).blue());
-        Ok(false)
+        let rules = quest.rules.iter().map(|rule| rule.as_ref().map(|rule| rule.id.clone())).collect();
+        let options = quest.options.iter().enumerate()
+            .map(|(i, (_, right))| chosen.contains(&(i + 1)) == *right)
+            .collect();
+        Ok(Some(Check { verdict, fillings, rules, options }))
     }
     
     fn readline(&mut self, prompt: &str) -> miette::Result<Option<String>> {
@@ -281,33 +952,26 @@ impl Quiz for Lexeme {
 
 impl Quiz for Orthogram {
     fn spell_question(&self) -> String {
-        let mut rnd = rand::thread_rng();
         match self {
-            Orthogram::Gap { answer: _, comment } => {
-                if let Some(comment) = comment {
-                    let comment = format!("({})", aid::spell_text(comment)).blue();
-                    format!("{}{}", "_".bold().yellow(), comment)
-                } else {
-                    format!("{}", "_".bold().yellow())
-                }
+            Orthogram::Gap { answers: _, rule: _, comment, explanation: _ } => {
+                format!("{}{}", "_".bold().yellow(), aid::spell_orthogram_comment(comment))
             },
-            Orthogram::Choice { right_answer, wrong_answers } => {
-                let mut answers = Vec::new();
-                answers.push(aid::spell_text(right_answer));
-                wrong_answers.iter().for_each(|item| answers.push(aid::spell_text(item)));
-                answers.shuffle(&mut rnd);
-                format!("{}", answers.join("/").underline().bold().yellow())
+            Orthogram::Choice { right_answers, wrong_answers, rule: _, comment, explanation: _ } => {
+                let answers: Vec<String> = aid::shuffled_variants(right_answers, wrong_answers).iter()
+                    .map(|answer| visible(answer))
+                    .collect();
+                format!("{}{}", answers.join("/").underline().bold().yellow(), aid::spell_orthogram_comment(comment))
             }
         }
     }
 
     fn spell_answer(&self) -> String {
         match self {
-            Orthogram::Gap { answer, comment: _ } => {
-                aid::spell_text(answer)
+            Orthogram::Gap { answers, rule: _, comment: _, explanation: _ } => {
+                aid::spell_text(&answers[0])
             },
-            Orthogram::Choice { right_answer, wrong_answers: _ } => {
-                aid::spell_text(right_answer)
+            Orthogram::Choice { right_answers, wrong_answers: _, rule: _, comment: _, explanation: _ } => {
+                aid::spell_text(&right_answers[0])
             }
         }
     }
@@ -323,4 +987,796 @@ pub(super) mod aid {
         }
         spelling
     }
+
+    /// Spell the right answer of the orthogram line, and find char ranges of its orthograms.
+    pub(super) fn spell_answer_with_ranges(text: &Vec<Lexeme>) -> (String, Vec<Range<usize>>) {
+        let mut spelling = String::new();
+        let mut ranges = Vec::new();
+        let mut len = 0;
+        for lexeme in text {
+            let lexeme_spelling = lexeme.spell_answer();
+            let lexeme_len = lexeme_spelling.chars().count();
+            if let Lexeme::Orthogram(_) = lexeme {
+                ranges.push(len..len + lexeme_len);
+            }
+            spelling += &lexeme_spelling;
+            len += lexeme_len;
+        }
+        (spelling, ranges)
+    }
+
+    /// Spell the comment of an orthogram shown after it, if there is one.
+    pub(super) fn spell_orthogram_comment(comment: &Option<Text>) -> String {
+        match comment {
+            Some(comment) => format!("({})", spell_text(comment)).blue().to_string(),
+            None => String::new()
+        }
+    }
+
+    /// Ids of the rules every orthogram of the line refers to.
+    pub(super) fn orthogram_rules(text: &[Lexeme]) -> Vec<Option<String>> {
+        text.iter().filter_map(|lexeme| match lexeme {
+            Lexeme::Orthogram(Orthogram::Gap { rule, .. } | Orthogram::Choice { rule, .. }) => {
+                Some(rule.as_ref().map(|rule| rule.spelling()[1..].to_string()))
+            },
+            Lexeme::Normal(_) => None
+        }).collect()
+    }
+
+    /// Text of the explanation of a line, if it's not empty.
+    pub(super) fn explanation(explanation: &Option<Token>) -> Option<&str> {
+        explanation.as_ref().map(|token| token.spelling()).filter(|spelling| !spelling.is_empty())
+    }
+
+    /// Explanations of every orthogram of the line.
+    pub(super) fn orthogram_explanations(text: &[Lexeme]) -> Vec<Option<String>> {
+        text.iter().filter_map(|lexeme| match lexeme {
+            Lexeme::Orthogram(Orthogram::Gap { explanation, .. } | Orthogram::Choice { explanation, .. }) => {
+                Some(explanation.as_ref().map(spell_text))
+            },
+            Lexeme::Normal(_) => None
+        }).collect()
+    }
+
+    pub(super) fn shuffled_variants(right_answers: &[Text], wrong_answers: &[Text]) -> Vec<String> {
+        let mut rnd = rand::thread_rng();
+        let mut answers = Vec::new();
+        right_answers.iter().for_each(|item| answers.push(spell_text(item)));
+        wrong_answers.iter().for_each(|item| answers.push(spell_text(item)));
+        answers.shuffle(&mut rnd);
+        answers
+    }
+
+    /// Spell the orthogram line with numbered variants of its choice orthograms, and
+    /// return the variants of every orthogram in the shown order (empty for gaps).
+    pub(super) fn spell_numbered_question(text: &Vec<Lexeme>) -> (String, Vec<Vec<String>>) {
+        let mut spelling = String::new();
+        let mut choices = Vec::new();
+        for lexeme in text {
+            match lexeme {
+                Lexeme::Orthogram(Orthogram::Choice { right_answers, wrong_answers, comment, .. }) => {
+                    let variants = shuffled_variants(right_answers, wrong_answers);
+                    let numbered: Vec<String> = variants.iter().enumerate()
+                        .map(|(i, variant)| {
+                            format!("{}{}", format!("{}:", i + 1).blue(), visible(variant).underline().bold().yellow())
+                        })
+                        .collect();
+                    spelling += &numbered.join("/");
+                    spelling += &spell_orthogram_comment(comment);
+                    choices.push(variants);
+                },
+                Lexeme::Orthogram(orthogram) => {
+                    spelling += &orthogram.spell_question();
+                    choices.push(Vec::new());
+                },
+                Lexeme::Normal(token) => spelling += token.spelling()
+            }
+        }
+        (spelling, choices)
+    }
+
+    /// Turn the user's selection like `2; 1; ie; 3` into fillings of orthograms. Numbers
+    /// select variants of choice orthograms, and gaps are filled with the typed text.
+    /// Items are separated by `;`, spaces around them are ignored unless the item is
+    /// made only of spaces. Extra items are appended to the last filling, so that it
+    /// becomes wrong.
+    pub(super) fn select_fillings(answer: &str, choices: &[Vec<String>]) -> Vec<String> {
+        let items: Vec<&str> = answer.split(';')
+            .map(|item| if item.trim().is_empty() { item } else { item.trim() })
+            .collect();
+        let mut fillings = Vec::new();
+        for (i, variants) in choices.iter().enumerate() {
+            let Some(item) = items.get(i) else {
+                fillings.push(String::new());
+                continue;
+            };
+            let variant = item.parse::<usize>().ok()
+                .and_then(|number| number.checked_sub(1))
+                .and_then(|index| variants.get(index));
+            fillings.push(variant.cloned().unwrap_or_else(|| item.to_string()));
+        }
+        if items.len() > choices.len() {
+            if let Some(last) = fillings.last_mut() {
+                *last = format!("{last};{}", items[choices.len()..].join(";"));
+            }
+        }
+        fillings
+    }
+
+    /// Numbers of the right options like `1 3`.
+    pub(super) fn option_numbers(options: impl Iterator<Item = bool>) -> String {
+        let numbers: Vec<String> = options.enumerate()
+            .filter(|(_, right)| *right)
+            .map(|(i, _)| (i + 1).to_string())
+            .collect();
+        numbers.join(" ")
+    }
+
+    /// Turn the user's selection like `3, 1` into the answer to a choice question with
+    /// the numbers in order, and return the numbers of the chosen options. Items that
+    /// aren't numbers of options are left in the answer, so that it becomes wrong.
+    pub(super) fn select_options(answer: &str, options: usize) -> (String, Vec<usize>) {
+        let mut chosen = Vec::new();
+        let mut others = Vec::new();
+        for item in answer.split(|c: char| c == ',' || c.is_whitespace()).filter(|item| !item.is_empty()) {
+            match item.parse::<usize>() {
+                Ok(number) if (1..=options).contains(&number) => chosen.push(number),
+                _ => others.push(item)
+            }
+        }
+        chosen.sort();
+        chosen.dedup();
+        let answer = option_numbers((1..=options).map(|number| chosen.contains(&number)));
+        let answer = std::iter::once(answer.as_str()).chain(others).filter(|item| !item.is_empty())
+            .collect::<Vec<_>>()
+            .join(" ");
+        (answer, chosen)
+    }
+
+    /// Accepted spellings of every orthogram of the line.
+    pub(super) fn orthogram_variants(text: &[Lexeme]) -> Vec<Vec<String>> {
+        text.iter().filter_map(|lexeme| match lexeme {
+            Lexeme::Orthogram(Orthogram::Gap { answers, .. }) => Some(answers.iter().map(spell_text).collect()),
+            Lexeme::Orthogram(Orthogram::Choice { right_answers, .. }) => {
+                Some(right_answers.iter().map(spell_text).collect())
+            },
+            Lexeme::Normal(_) => None
+        }).collect()
+    }
+
+    /// Find the accepted answer closest to the user's one: a matching one if it
+    /// exists, or the one with the least distance. Orthograms of the answer are
+    /// spelled with the variants the user has chosen if they are right. Returns the
+    /// answer with char ranges of its orthograms.
+    pub(super) fn closest_answer(answer: &str, quest: &Question) -> (String, Vec<Range<usize>>) {
+        if !quest.orthograms.is_empty() {
+            let fillings = align::grade(answer, &quest.right_answer, &quest.orthograms);
+            let chosen: Vec<String> = quest.variants.iter().zip(&fillings)
+                .map(|(variants, filling)| {
+                    let variant = variants.iter().find(|variant| quest.policy.matches(&filling.text, variant));
+                    variant.unwrap_or(&variants[0]).clone()
+                })
+                .collect();
+            return fill_orthograms(&quest.right_answer, &quest.orthograms, &chosen);
+        }
+        let right_answer = std::iter::once(&quest.right_answer)
+            .chain(&quest.alternatives)
+            .min_by_key(|right_answer| {
+                if quest.policy.matches(answer, right_answer) {
+                    0
+                } else {
+                    quest.policy.distance(answer, right_answer) + 1
+                }
+            })
+            .expect("there is at least one right answer");
+        (right_answer.clone(), Vec::new())
+    }
+
+    /// Spell the stress question without stress marks, with the stressed words
+    /// highlighted, and find char ranges of these words in the right answer.
+    pub(super) fn spell_stress_question(text: &Text) -> (String, Vec<Range<usize>>) {
+        let mut spelling = String::new();
+        let mut ranges = Vec::new();
+        let mut len = 0;
+        for token in text {
+            let token_len = token.spelling().chars().count();
+            if has_stress(token.spelling()) {
+                spelling += &unstressed(token.spelling()).yellow().bold().to_string();
+                ranges.push(len..len + token_len);
+            } else {
+                spelling += token.spelling();
+            }
+            len += token_len;
+        }
+        (spelling, ranges)
+    }
+
+    /// Spell the segment line with its words highlighted and without boundaries, and
+    /// its right answer with boundaries. Returns them with char ranges of the words in
+    /// the right answer.
+    pub(super) fn spell_segments(text: &[SegmentLexeme]) -> (String, String, Vec<Range<usize>>) {
+        let mut question = String::new();
+        let mut right_answer = String::new();
+        let mut ranges = Vec::new();
+        for lexeme in text {
+            match lexeme {
+                SegmentLexeme::Normal(token) => {
+                    question += token.spelling();
+                    right_answer += token.spelling();
+                },
+                SegmentLexeme::Word(segments) => {
+                    let segments: Vec<String> = segments.iter().map(spell_text).collect();
+                    question += &segments.concat().yellow().bold().to_string();
+                    let start = right_answer.chars().count();
+                    right_answer += &segments.join(&BOUNDARY.to_string());
+                    ranges.push(start..right_answer.chars().count());
+                }
+            }
+        }
+        (question, right_answer, ranges)
+    }
+
+    /// Compare boundaries of segments in the user's word with the right ones. Returns
+    /// the word with only the missed boundaries and the word with only the extra ones,
+    /// each is empty if there are none. Returns `None` if the letters of the words
+    /// differ, so boundaries can't be compared.
+    pub(super) fn boundaries(word: &str, right_word: &str) -> Option<(String, String)> {
+        let split = |word: &str| -> (String, Vec<usize>) {
+            let mut letters = String::new();
+            let mut positions = Vec::new();
+            for c in word.chars() {
+                if c == BOUNDARY {
+                    positions.push(letters.chars().count());
+                } else {
+                    letters.push(c);
+                }
+            }
+            (letters, positions)
+        };
+        let (letters, positions) = split(word);
+        let (right_letters, right_positions) = split(right_word);
+        if letters != right_letters {
+            return None;
+        }
+        let mark = |marked: Vec<usize>| -> String {
+            if marked.is_empty() {
+                return String::new();
+            }
+            let mut spelling = String::new();
+            for (i, c) in letters.chars().enumerate() {
+                if marked.contains(&i) {
+                    spelling.push(BOUNDARY);
+                }
+                spelling.push(c);
+            }
+            spelling
+        };
+        let missed = right_positions.iter().filter(|pos| !positions.contains(pos)).copied().collect();
+        let extra = positions.iter().filter(|pos| !right_positions.contains(pos)).copied().collect();
+        Some((mark(missed), mark(extra)))
+    }
+
+    /// Place the stress mark into the word: after its vowel with the number given in
+    /// the item, or where the user's item has it. `+` after a vowel counts as the mark.
+    pub(super) fn place_stress(word: &str, item: &str) -> String {
+        let Ok(number) = item.trim().parse::<usize>() else {
+            return item.trim().replace('+', &STRESS.to_string());
+        };
+        let mut spelling = String::new();
+        let mut vowels = 0;
+        for c in word.chars() {
+            spelling.push(c);
+            if is_vowel(c) {
+                vowels += 1;
+                if vowels == number {
+                    spelling.push(STRESS);
+                }
+            }
+        }
+        spelling
+    }
+
+    /// Turn the user's items (numbers of stressed vowels or stressed words) into the
+    /// stressed words of the question.
+    pub(super) fn stressed_fillings<S: AsRef<str>>(quest: &Question, items: &[S]) -> Vec<String> {
+        quest.variants.iter().enumerate().map(|(i, variants)| {
+            let word = unstressed(&variants[0]);
+            place_stress(&word, items.get(i).map_or("", |item| item.as_ref()))
+        }).collect()
+    }
+
+    /// Build the whole answer line of the stress question from the user's answer. The
+    /// user can type all words of the line or only the stressed ones, or just numbers
+    /// of the stressed vowels. Other words are taken from the right answer.
+    pub(super) fn stressed_answer(answer: &str, quest: &Question) -> String {
+        let split = |text: &str| -> Vec<String> {
+            text.split(|c: char| !(c.is_alphanumeric() || c == '+' || crate::compare::aid::is_combining_mark(c)))
+                .filter(|word| !word.is_empty())
+                .map(String::from)
+                .collect()
+        };
+        let items = split(answer);
+        let words = split(&quest.right_answer);
+        let items: Vec<String> = if items.len() == quest.orthograms.len() {
+            items
+        } else {
+            words.iter().enumerate()
+                .filter(|(_, word)| has_stress(word))
+                .map(|(i, _)| items.get(i).cloned().unwrap_or_default())
+                .collect()
+        };
+        let fillings = stressed_fillings(quest, &items);
+        fill_orthograms(&quest.right_answer, &quest.orthograms, &fillings).0
+    }
+
+    /// Build the whole answer line replacing orthograms of the right answer with the
+    /// user's fillings, and find char ranges of the fillings in it.
+    pub(super) fn fill_orthograms(right_answer: &str, orthograms: &[Range<usize>], fillings: &[String])
+        -> (String, Vec<Range<usize>>) {
+        let chars: Vec<char> = right_answer.chars().collect();
+        let mut answer = String::new();
+        let mut ranges = Vec::new();
+        let mut len = 0;
+        let mut pos = 0;
+        for (range, filling) in orthograms.iter().zip(fillings) {
+            answer.extend(&chars[pos..range.start]);
+            len += range.start - pos;
+            answer += filling;
+            ranges.push(len..len + filling.chars().count());
+            len = ranges[ranges.len() - 1].end;
+            pos = range.end;
+        }
+        answer.extend(&chars[pos..]);
+        (answer, ranges)
+    }
+
+    /// Spell the right answer with its orthograms colored according to the user's fillings.
+    /// A wrong filling is shown crossed out before all the right spellings.
+    pub(super) fn spell_checked(right_answer: &str, orthograms: &[Range<usize>], fillings: &[Filling],
+                                variants: &[Vec<String>]) -> String {
+        let chars: Vec<char> = right_answer.chars().collect();
+        let mut spelling = String::new();
+        let mut pos = 0;
+        for ((range, filling), variants) in orthograms.iter().zip(fillings).zip(variants) {
+            spelling.extend(&chars[pos..range.start]);
+            let right: String = chars[range.clone()].iter().collect();
+            if filling.right {
+                spelling += &visible(&right).green().bold().to_string();
+            } else {
+                let variants: Vec<String> = variants.iter().map(|variant| visible(variant)).collect();
+                spelling += &visible(&filling.text).red().strikethrough().to_string();
+                spelling += &variants.join("/").green().bold().to_string();
+            }
+            pos = range.end;
+        }
+        spelling.extend(&chars[pos..]);
+        spelling
+    }
+
+    /// Take the header (leading public comments) of a unit and the settings of its
+    /// `#!set` directives out of its lines. Shebangs are dropped.
+    pub(super) fn split_header(quests: Vec<Quest>) -> (Vec<String>, Settings, Vec<Quest>) {
+        let mut header = Vec::new();
+        let mut settings = Settings::default();
+        let mut lines = Vec::new();
+        for quest in quests {
+            match &quest.line {
+                Line::Set(setting) => settings.set(setting.clone()),
+                Line::Rule(_) => (),
+                Line::PubComment(token) if token.is_shebang() => (),
+                Line::PubComment(token) if lines.is_empty() => header.push(token.spelling().to_string()),
+                _ => lines.push(quest)
+            }
+        }
+        (header, settings, lines)
+    }
+
+    /// Leave only the first `tests` statements of the lines.
+    pub(super) fn take_tests(lines: &mut Vec<Quest>, tests: usize) {
+        let mut taken = 0;
+        lines.retain(|quest| {
+            if quest.line.is_stmt() {
+                taken += 1;
+                taken <= tests
+            } else {
+                taken < tests
+            }
+        });
+    }
+
+    /// Split `limit` questions between units according to their weights in percents.
+    /// Units without weight share the rest equally. Quotas are rounded by the largest
+    /// remainders, and a unit with fewer than its quota `available` statements gives
+    /// the shortfall to the other units.
+    pub(super) fn quotas(limit: usize, weights: &[Option<usize>], available: &[usize]) -> Vec<usize> {
+        let unweighted = weights.iter().filter(|weight| weight.is_none()).count();
+        let weighted: usize = weights.iter().flatten().map(|weight| limit * weight).sum();
+        let rest = (limit * 100).saturating_sub(weighted);
+        let shares: Vec<usize> = weights.iter()
+            .map(|weight| weight.map_or(rest, |weight| limit * weight * unweighted.max(1)))
+            .collect();
+        let scale = 100 * unweighted.max(1);
+        let mut target = limit.min((shares.iter().sum::<usize>() + scale / 2) / scale);
+        let mut quotas = vec![0; weights.len()];
+        let mut open: Vec<usize> = (0..weights.len()).collect();
+        loop {
+            let open_shares: Vec<usize> = open.iter().map(|&i| shares[i]).collect();
+            let apportioned = apportion(target, &open_shares);
+            let full: Vec<usize> = open.iter().zip(&apportioned)
+                .filter(|(&i, &quota)| quota > available[i])
+                .map(|(&i, _)| i)
+                .collect();
+            if full.is_empty() {
+                for (&i, quota) in open.iter().zip(apportioned) {
+                    quotas[i] = quota;
+                }
+                return quotas;
+            }
+            for &i in &full {
+                quotas[i] = available[i];
+                target -= available[i];
+            }
+            open.retain(|i| !full.contains(i));
+        }
+    }
+
+    /// Split `target` proportionally to the shares, giving the units left after
+    /// rounding down to the ones with the largest remainders.
+    fn apportion(target: usize, shares: &[usize]) -> Vec<usize> {
+        let total: usize = shares.iter().sum();
+        if total == 0 {
+            return vec![0; shares.len()];
+        }
+        let mut quotas: Vec<usize> = shares.iter().map(|share| target * share / total).collect();
+        let mut order: Vec<usize> = (0..shares.len()).collect();
+        order.sort_by_key(|&i| std::cmp::Reverse(target * shares[i] % total));
+        let left = target - quotas.iter().sum::<usize>();
+        for &i in order.iter().take(left) {
+            quotas[i] += 1;
+        }
+        quotas
+    }
+
+    /// Leave `tests` statements of the lines chosen at random without replacement,
+    /// keeping their order. Other lines are left as they are.
+    pub(super) fn sample_tests(lines: &mut Vec<Quest>, tests: usize) {
+        let stmts = lines.iter().filter(|quest| quest.line.is_stmt()).count();
+        if stmts <= tests {
+            return;
+        }
+        let mut rng = rand::thread_rng();
+        let chosen: HashSet<usize> = rand::seq::index::sample(&mut rng, stmts, tests).into_iter().collect();
+        let mut i = 0;
+        lines.retain(|quest| {
+            if !quest.line.is_stmt() {
+                return true;
+            }
+            i += 1;
+            chosen.contains(&(i - 1))
+        });
+    }
+
+    /// Mix lines of the units taking a statement from every unit in turn. Other
+    /// lines go together with the statement following them.
+    pub(super) fn interleave(units: Vec<Vec<Quest>>) -> Vec<Quest> {
+        let mut units: Vec<VecDeque<Vec<Quest>>> = units.into_iter().map(|quests| {
+            let mut groups = VecDeque::new();
+            let mut group = Vec::new();
+            for quest in quests {
+                let is_stmt = quest.line.is_stmt();
+                group.push(quest);
+                if is_stmt {
+                    groups.push_back(std::mem::take(&mut group));
+                }
+            }
+            if !group.is_empty() {
+                groups.push_back(group);
+            }
+            groups
+        }).collect();
+        let mut lines = Vec::new();
+        while units.iter().any(|groups| !groups.is_empty()) {
+            for groups in &mut units {
+                if let Some(group) = groups.pop_front() {
+                    lines.extend(group);
+                }
+            }
+        }
+        lines
+    }
+
+    /// Position in the queue a few questions later, where a wrongly answered line is put to.
+    pub(super) fn retry_position(quests: &VecDeque<Quest>) -> usize {
+        const RETRY_DELAY: usize = 3;
+        let mut questions = 0;
+        for (i, quest) in quests.iter().enumerate() {
+            if questions == RETRY_DELAY {
+                return i;
+            }
+            if quest_key(&quest.line).is_some() {
+                questions += 1;
+            }
+        }
+        quests.len()
+    }
+
+    /// Spelling of the text with the right answers of its orthograms in brackets.
+    fn lexemes_key(text: &[Lexeme]) -> String {
+        let mut key = String::new();
+        for lexeme in text {
+            match lexeme {
+                Lexeme::Normal(token) => key += token.spelling(),
+                Lexeme::Orthogram(orthogram) => key += &format!("[{}]", orthogram.spell_answer())
+            }
+        }
+        key
+    }
+
+    /// Key identifying the question of the line in the progress store, or `None`
+    /// if the line isn't a question.
+    pub fn quest_key(line: &Line) -> Option<String> {
+        match line {
+            Line::PlainStmt { text, tags: _, comment: _, explanation: _ } => Some(spell_text(text)),
+            Line::StressStmt { text, tags: _, comment: _, explanation: _ } => Some(spell_text(text)),
+            Line::SegmentStmt { text, tags: _, comment: _, explanation: _ } => {
+                let key = text.iter().map(|lexeme| match lexeme {
+                    SegmentLexeme::Normal(token) => token.spelling().to_string(),
+                    SegmentLexeme::Word(segments) => {
+                        format!("{{{}}}", segments.iter().map(spell_text).collect::<Vec<_>>().join("|"))
+                    }
+                }).collect();
+                Some(key)
+            },
+            Line::TranslationStmt { original, translations, bidirectional: _, tags: _, comment: _, explanation: _ } => {
+                let translations: Vec<String> = translations.iter().map(spell_text).collect();
+                Some(format!("{} -> {}", spell_text(original), translations.join(" | ")))
+            },
+            Line::ComplexStmt { text, tags: _, comment: _, explanation: _ } => Some(lexemes_key(text)),
+            Line::ChoiceStmt { question, right_options, .. } => {
+                let options: Vec<String> = right_options.iter().map(spell_text).collect();
+                Some(format!("? {} -> {}", spell_text(question), options.join(" | ")))
+            },
+            Line::ComplexTranslationStmt { original, translation, .. } => {
+                Some(format!("{} -> {}", lexemes_key(original), lexemes_key(translation)))
+            },
+            Line::PubComment(_) | Line::Heading { .. } | Line::Set(_) | Line::Rule(_) | Line::Include { .. } |
+            Line::Empty => None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn quests(source: &str) -> Vec<Quest> {
+        let lines = Parser::new("test", source).parse().unwrap();
+        lines.into_iter().map(|line| Quest { script: Rc::from("test"), unit: 0, line, retry: false }).collect()
+    }
+
+    fn quiz(settings: Settings, source: &str) -> QuizMachine {
+        let quiz = QuizMachine::new(settings, Instant::now());
+        let lines = Parser::new("test", source).parse().unwrap();
+        quiz.append("test.tort", None, vec![Script { path: "test.tort".into(), key: "test.tort".into(), lines }]);
+        quiz
+    }
+
+    fn stmts(quests: &[Quest]) -> Vec<String> {
+        quests.iter().filter_map(|quest| match &quest.line {
+            Line::PlainStmt { text, .. } => Some(aid::spell_text(text)),
+            _ => None
+        }).collect()
+    }
+
+    fn stress_question(source: &str) -> Question<'static> {
+        let lines = Parser::new("test", &format!("#!set stress = true\n{source}")).parse().unwrap();
+        let Some(Line::StressStmt { text, .. }) = lines.last() else {
+            panic!("expected a stress statement");
+        };
+        let right_answer = aid::spell_text(text);
+        let (question, orthograms) = aid::spell_stress_question(text);
+        let chars: Vec<char> = right_answer.chars().collect();
+        let variants = orthograms.iter().map(|range| vec![chars[range.clone()].iter().collect()]).collect();
+        Question::new("Stress", "Your answer", question, right_answer, Policy::strict(), None)
+            .with_orthograms(orthograms, variants)
+            .with_stress(true)
+    }
+
+    #[test]
+    fn place_stress() {
+        let cases = [
+            ("малако", "3", "малако\u{301}"),
+            ("малако", " 1 ", "ма\u{301}лако"),
+            ("малако", "4", "малако"),
+            ("малако", "малако+", "малако\u{301}"),
+            ("малако", "мала\u{301}ко", "мала\u{301}ко"),
+            ("Познан\u{301}", "1", "По\u{301}знан\u{301}"),
+        ];
+        for (word, item, stressed) in cases {
+            assert_eq!(aid::place_stress(word, item), stressed, "{word} {item}");
+        }
+    }
+
+    #[test]
+    fn stressed_answer() {
+        let quest = stress_question("Я люблю\u{301} малако\u{301}.\n");
+        let cases = [
+            ("2 3", "Я люблю\u{301} малако\u{301}."),
+            ("1 2", "Я лю\u{301}блю мала\u{301}ко."),
+            ("люблю+ малако+", "Я люблю\u{301} малако\u{301}."),
+            ("Я люблю+ мала+ко.", "Я люблю\u{301} мала\u{301}ко."),
+            ("лю\u{301}блю малако\u{301}", "Я лю\u{301}блю малако\u{301}."),
+        ];
+        for (answer, line) in cases {
+            assert_eq!(aid::stressed_answer(answer, &quest), line, "{answer}");
+        }
+    }
+
+    #[test]
+    fn select_fillings() {
+        let choices = [vec!["e".to_string(), "ie".to_string()], Vec::new(), vec!["a".to_string(), "o".to_string()]];
+        let cases = [
+            ("2; ee; 1", vec!["ie", "ee", "a"]),
+            ("2;;1", vec!["ie", "", "a"]),
+            ("1; a b ;2", vec!["e", "a b", "o"]),
+            ("1; ;2", vec!["e", " ", "o"]),
+            ("3; 1", vec!["3", "1", ""]),
+            ("1; x; 2; y", vec!["e", "x", "o;y"]),
+        ];
+        for (answer, fillings) in cases {
+            assert_eq!(aid::select_fillings(answer, &choices), fillings, "{answer}");
+        }
+    }
+
+    #[test]
+    fn option_numbers() {
+        assert_eq!(aid::option_numbers([false, true, false, true].into_iter()), "2 4");
+        assert_eq!(aid::option_numbers([true].into_iter()), "1");
+        assert_eq!(aid::option_numbers([false, false].into_iter()), "");
+    }
+
+    #[test]
+    fn select_options() {
+        let cases = [
+            ("2", "2", vec![2]),
+            ("3, 1", "1 3", vec![1, 3]),
+            (" 3 1 3 ", "1 3", vec![1, 3]),
+            ("1 5", "1 5", vec![1]),
+            ("0 a,2", "2 0 a", vec![2]),
+            ("", "", vec![]),
+        ];
+        for (answer, selection, chosen) in cases {
+            assert_eq!(aid::select_options(answer, 3), (selection.to_string(), chosen), "{answer}");
+        }
+    }
+
+    #[test]
+    fn split_header() {
+        let source = "#!/usr/bin/env tort\n#! Hello\n#!set tests = 2\na\n";
+        let (header, settings, lines) = aid::split_header(quests(source));
+        assert_eq!(header, vec!["Hello".to_string()]);
+        assert_eq!(settings.tests, Some(2));
+        assert_eq!(stmts(&lines).len(), 1);
+
+        let (header, _, lines) = aid::split_header(quests("#! Hello\n#! world\na\n#! Later\n"));
+        assert_eq!(header, vec!["Hello".to_string(), "world".to_string()]);
+        assert_eq!(lines.len(), 2);
+        assert!(matches!(&lines[1].line, Line::PubComment(token) if token.spelling() == "Later"));
+    }
+
+    #[test]
+    fn take_tests() {
+        let mut lines = quests("## One\na\nb\n## Two\nc\n");
+        aid::take_tests(&mut lines, 2);
+        assert_eq!(lines.len(), 3);
+        assert_eq!(stmts(&lines), vec!["a", "b"]);
+
+        let mut lines = quests("a\n## Two\nb\n");
+        aid::take_tests(&mut lines, 5);
+        assert_eq!(lines.len(), 3);
+    }
+
+    #[test]
+    fn interleave() {
+        let lines = aid::interleave(vec![quests("a\nb\nc\n"), quests("## Two\nd\n")]);
+        assert_eq!(stmts(&lines), vec!["a", "d", "b", "c"]);
+        assert!(matches!(&lines[1].line, Line::Heading { title, .. } if title == "Two"));
+    }
+
+    #[test]
+    fn retry_position() {
+        // three questions are asked before the retry, headings aren't counted
+        assert_eq!(aid::retry_position(&quests("a\n## Two\nb\nc\nd\n").into()), 4);
+        assert_eq!(aid::retry_position(&quests("a\nb\n").into()), 2);
+        assert_eq!(aid::retry_position(&VecDeque::new()), 0);
+    }
+
+    #[test]
+    fn nothing_due() {
+        let quiz = quiz(Settings::default(), "a\nb\n");
+        quiz.set_progress(Progress::open(&std::env::temp_dir().join("tort-nothing-due.tsv")).unwrap());
+        quiz.due_only(0).unwrap();
+        assert!(quiz.pre_run().iter().all(|quest| !quest.line.is_stmt()));
+        let stats = &quiz.inner.borrow().stats;
+        assert_eq!(stats.to_run_tests, 0);
+        assert_eq!(stats.empty_note(), Some("Nothing is due today"));
+
+        let mut stats = AnswerStatistic::new(Instant::now());
+        assert_eq!(stats.empty_note(), Some("No tests are done"));
+        stats.done_tests = 1;
+        assert_eq!(stats.empty_note(), None);
+    }
+
+    #[test]
+    fn tests_precedence() {
+        let to_run_tests = |settings: Settings| {
+            let quiz = quiz(settings, "#!set tests = 1\na\nb\nc\nd\n");
+            quiz.pre_run();
+            let to_run_tests = quiz.inner.borrow().stats.to_run_tests;
+            to_run_tests
+        };
+        assert_eq!(to_run_tests(Settings::default()), 1);
+        assert_eq!(to_run_tests(Settings { tests: Some(3), ..Settings::default() }), 3);
+    }
+
+    #[test]
+    fn count_almost() {
+        let mut stats = AnswerStatistic::new(Instant::now());
+        stats.units = vec![("test.tort".into(), Tally::default())];
+        let quests = quests("a -> b @words\n");
+        for verdict in [Verdict::Right, Verdict::Almost, Verdict::Wrong] {
+            let check = Check { verdict, fillings: Vec::new(), rules: Vec::new(), options: Vec::new() };
+            stats.count(&check, &quests[0]);
+        }
+        let tally = Tally { right: 1, almost: 1, done: 3 };
+        assert_eq!(stats.tags["words"], tally);
+        assert_eq!(stats.units[0].1, tally);
+        assert_eq!((stats.right_answers, stats.almost_answers, stats.wrong_answers), (1, 1, 1));
+    }
+
+    #[test]
+    fn boundaries() {
+        let cases = [
+            ("ма-ла-ко", "ма-ла-ко", Some(("", ""))),
+            ("мала-ко", "ма-ла-ко", Some(("ма-лако", ""))),
+            ("ма-л-ако", "ма-ла-ко", Some(("мала-ко", "мал-ако"))),
+            ("малако", "ма-ла-ко", Some(("ма-ла-ко", ""))),
+            ("ма-ла-ка", "ма-ла-ко", None),
+        ];
+        for (word, right_word, boundaries) in cases {
+            let boundaries = boundaries.map(|(missed, extra)| (missed.to_string(), extra.to_string()));
+            assert_eq!(aid::boundaries(word, right_word), boundaries, "{word}");
+        }
+    }
+
+    #[test]
+    fn quotas() {
+        assert_eq!(aid::quotas(30, &[Some(70), None, None], &[100, 100, 100]), vec![21, 5, 4]);
+        assert_eq!(aid::quotas(10, &[Some(33), Some(33), Some(34)], &[10, 10, 10]), vec![3, 3, 4]);
+        assert_eq!(aid::quotas(10, &[Some(25), Some(25), Some(50)], &[10, 10, 10]), vec![3, 2, 5]);
+        assert_eq!(aid::quotas(10, &[Some(50)], &[10]), vec![5]);
+        assert_eq!(aid::quotas(10, &[Some(30), Some(30)], &[10, 10]), vec![3, 3]);
+        assert_eq!(aid::quotas(10, &[Some(80), None, None], &[2, 10, 10]), vec![2, 4, 4]);
+        assert_eq!(aid::quotas(10, &[Some(50), Some(30), Some(20)], &[10, 1, 10]), vec![6, 1, 3]);
+        assert_eq!(aid::quotas(10, &[Some(50), None], &[3, 4]), vec![3, 4]);
+    }
+
+    #[test]
+    fn sample_tests() {
+        let source = "## One\na\nb\n## Two\nc\nd\n";
+        let mut lines = quests(source);
+        aid::sample_tests(&mut lines, 2);
+        let sampled = stmts(&lines);
+        assert_eq!(sampled.len(), 2);
+        let all = stmts(&quests(source));
+        let mut positions = sampled.iter().map(|stmt| all.iter().position(|line| line == stmt).unwrap());
+        let first = positions.next().unwrap();
+        assert!(first < positions.next().unwrap());
+        assert_eq!(lines.iter().filter(|quest| matches!(quest.line, Line::Heading { .. })).count(), 2);
+
+        let mut lines = quests(source);
+        aid::sample_tests(&mut lines, 5);
+        assert_eq!(stmts(&lines), all);
+    }
 }
@@ -0,0 +1,282 @@
+use miette::{MietteDiagnostic, Report, Result};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::diag::Diag;
+use crate::source::{SourceMap, SourceRange};
+use crate::syntax::{Line, Orthogram, Parser, Section};
+
+/// Lines of a script file.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Script {
+    /// Path to the file as it's given or resolved from an include directive
+    pub path: PathBuf,
+    /// Canonical path of the file, used as a key in the progress store
+    pub key: String,
+    pub lines: Vec<Line>,
+}
+
+impl Script {
+    /// Leave only the sections of the expanded scripts whose titles contain one of the
+    /// names, see [`Section::select`]. A section of a script lasts into the scripts it
+    /// includes, and sections of the included scripts can be selected too. Returns
+    /// `None` if no section matches.
+    pub fn select_sections(mut scripts: Vec<Script>, names: &[String]) -> Option<Vec<Script>> {
+        let lines: Vec<(usize, Line)> = scripts.iter_mut()
+            .enumerate()
+            .flat_map(|(index, script)| std::mem::take(&mut script.lines).into_iter().map(move |line| (index, line)))
+            .collect();
+        let mut selected: Vec<Script> = Vec::new();
+        let mut last = None;
+        for (index, line) in Section::new(lines).select(names)?.flatten() {
+            if last != Some(index) {
+                let Script { path, key, .. } = &scripts[index];
+                selected.push(Script { path: path.clone(), key: key.clone(), lines: Vec::new() });
+                last = Some(index);
+            }
+            selected.last_mut().unwrap().lines.push(line);
+        }
+        Some(selected)
+    }
+}
+
+/// Loader of scripts that follows their `#!include` directives.
+#[derive(Debug, Default)]
+pub struct Loader {
+    sources: SourceMap,
+    /// Keys and names of the scripts being expanded, to detect include cycles
+    stack: Vec<(String, String)>,
+    /// Ids of the rules of the checked scripts with the key and the name of their
+    /// script and the span of their directive
+    rules: HashMap<String, (String, String, SourceRange)>,
+}
+
+impl Loader {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Read and parse the script. Its include directives are left as they are till
+    /// [`Loader::expand`] is called.
+    pub fn parse(&mut self, path: &Path) -> Result<Script> {
+        let source = self.sources.load(path).map_err(|err| aid::read_error(path, err))?;
+        aid::parse(path, &source)
+    }
+
+    /// Replace include directives of the script with the scripts they include,
+    /// recursively. Returns the parts of all the scripts in order of their lines.
+    pub fn expand(&mut self, script: Script) -> Result<Vec<Script>> {
+        let name = script.path.display().to_string();
+        self.stack.push((script.key.clone(), name.clone()));
+        let scripts = self.expand_includes(script, &name);
+        self.stack.pop();
+        scripts
+    }
+
+    /// Check that the orthograms of the scripts refer only to the rules defined in them,
+    /// and that an id isn't defined by two directives of all the checked scripts.
+    pub fn check_rules(&mut self, scripts: &[Script]) -> Result<()> {
+        for script in scripts {
+            let name = script.path.display().to_string();
+            for line in &script.lines {
+                let Line::Rule(rule) = line else { continue };
+                let defined = (script.key.clone(), name.clone(), rule.span.clone());
+                match self.rules.get(&rule.id) {
+                    Some((key, first_name, span)) if *key != script.key || *span != rule.span => {
+                        let source = self.sources.get(&name).expect("the script must be loaded before checking");
+                        return Err(Diag::new(&name, &source).duplicate_rule(rule.span.clone(), &rule.id, first_name));
+                    },
+                    Some(_) => (),
+                    None => {
+                        self.rules.insert(rule.id.clone(), defined);
+                    }
+                }
+            }
+        }
+        let rules: Vec<&str> = scripts.iter()
+            .flat_map(|script| &script.lines)
+            .filter_map(|line| if let Line::Rule(rule) = line { Some(rule.id.as_str()) } else { None })
+            .collect();
+        for script in scripts {
+            for line in &script.lines {
+                for rule in line.orthograms().into_iter().filter_map(Orthogram::rule) {
+                    if !rules.contains(&&rule.spelling()[1..]) {
+                        let name = script.path.display().to_string();
+                        let source = self.sources.get(&name).expect("the script must be loaded before checking");
+                        return Err(Diag::new(&name, &source).unknown_rule(rule));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn expand_includes(&mut self, script: Script, name: &str) -> Result<Vec<Script>> {
+        let source = self.sources.get(name).expect("the script must be loaded before expanding");
+        let diag = Diag::new(name, &source);
+        let dir = script.path.parent().unwrap_or(Path::new(""));
+        let mut scripts = Vec::new();
+        let mut lines = Vec::new();
+        for line in script.lines {
+            let Line::Include { path, span } = line else {
+                lines.push(line);
+                continue;
+            };
+            let path = dir.join(path);
+            let included_source = self.sources.load(&path).map_err(|err| diag.unreadable_include(span.clone(), &err))?;
+            let key = aid::key(&path);
+            if let Some(start) = self.stack.iter().position(|(k, _)| *k == key) {
+                let mut cycle: Vec<String> = self.stack[start..].iter().map(|(_, name)| name.clone()).collect();
+                cycle.push(path.display().to_string());
+                return Err(diag.include_cycle(span, &cycle));
+            }
+            let included = aid::parse(&path, &included_source).map_err(|err| err.wrap_err(diag.included_from(&span)))?;
+            if !lines.is_empty() {
+                let lines = std::mem::take(&mut lines);
+                scripts.push(Script { path: script.path.clone(), key: script.key.clone(), lines });
+            }
+            scripts.extend(self.expand(included).map_err(|err| err.wrap_err(diag.included_from(&span)))?);
+        }
+        if !lines.is_empty() {
+            scripts.push(Script { path: script.path, key: script.key, lines });
+        }
+        Ok(scripts)
+    }
+}
+
+pub(super) mod aid {
+    use super::*;
+
+    pub fn parse(path: &Path, source: &str) -> Result<Script> {
+        let name = path.display().to_string();
+        let lines = Parser::new(&name, source).parse()?;
+        Ok(Script { path: path.to_owned(), key: key(path), lines })
+    }
+
+    pub fn key(path: &Path) -> String {
+        path.canonicalize().unwrap_or_else(|_| path.to_owned()).display().to_string()
+    }
+
+    pub fn read_error(path: &Path, err: std::io::Error) -> Report {
+        MietteDiagnostic::new(format!("can't read the script `{}`: {err}", path.display()))
+            .with_severity(miette::Severity::Error).into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn write_scripts(dir: &str, scripts: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("tort-{dir}-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("common")).unwrap();
+        for (name, source) in scripts {
+            std::fs::write(dir.join(name), source).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn expand_includes() {
+        let dir = write_scripts("include", &[
+            ("lesson.tort", "a\n#!include common/words.tort\nb\n"),
+            ("common/words.tort", "c -> d\n#!include more.tort\n"),
+            ("common/more.tort", "e\n"),
+        ]);
+        let mut loader = Loader::new();
+        let script = loader.parse(&dir.join("lesson.tort")).unwrap();
+        assert_eq!(script.lines.len(), 3);
+        let scripts = loader.expand(script).unwrap();
+        let lengths: Vec<usize> = scripts.iter().map(|script| script.lines.len()).collect();
+        assert_eq!(lengths, [1, 1, 1, 1]);
+        assert!(scripts[1].key.ends_with("words.tort"));
+        assert!(scripts[2].key.ends_with("more.tort"));
+        assert_eq!(scripts[0].key, scripts[3].key);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn include_errors() {
+        let dir = write_scripts("include-errors", &[
+            ("cycle.tort", "#! Header\n#!include common/cycle.tort\n"),
+            ("common/cycle.tort", "#!include ../cycle.tort\n"),
+            ("missing.tort", "#!include nothing.tort\n"),
+            ("bad.tort", "a\n#!include common/bad.tort\n"),
+            ("common/bad.tort", "a ->]\n"),
+        ]);
+        let mut loader = Loader::new();
+        for name in ["cycle.tort", "missing.tort", "bad.tort"] {
+            let script = loader.parse(&dir.join(name)).unwrap();
+            assert!(loader.expand(script).is_err());
+        }
+        let script = loader.parse(&dir.join("bad.tort")).unwrap();
+        let err = loader.expand(script).unwrap_err();
+        assert!(err.to_string().starts_with("in a script included from"));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn select_sections() {
+        let dir = write_scripts("sections", &[
+            ("lesson.tort", "#! Header\na\n## Verbs\nb\n#!include common/nouns.tort\n## Numerals\nc\n"),
+            ("common/nouns.tort", "d\n## Nouns\ne\n### Plural\nf\n"),
+        ]);
+        let mut loader = Loader::new();
+        let script = loader.parse(&dir.join("lesson.tort")).unwrap();
+        let scripts = loader.expand(script).unwrap();
+        let spell = |scripts: &[Script]| -> Vec<Vec<String>> {
+            scripts.iter().map(|script| script.lines.iter().map(|line| match line {
+                Line::Heading { title, .. } => title.clone(),
+                Line::PubComment(comment) => comment.spelling().to_owned(),
+                Line::PlainStmt { text, .. } => text[0].spelling().to_owned(),
+                line => panic!("unexpected line {line:?}"),
+            }).collect()).collect()
+        };
+
+        let selected = Script::select_sections(scripts.clone(), &["plural".into()]).unwrap();
+        assert_eq!(spell(&selected), [vec!["Header"], vec!["Nouns", "Plural", "f"]]);
+        assert!(selected[1].key.ends_with("nouns.tort"));
+        let selected = Script::select_sections(scripts.clone(), &["verbs".into()]).unwrap();
+        assert_eq!(spell(&selected), [vec!["Header", "Verbs", "b"], vec!["d"]]);
+        let selected = Script::select_sections(scripts.clone(), &["nouns".into(), "numerals".into()]).unwrap();
+        assert_eq!(spell(&selected), [vec!["Header"], vec!["Nouns", "e", "Plural", "f"], vec!["Numerals", "c"]]);
+        assert_eq!(selected[0].key, selected[2].key);
+        assert_eq!(Script::select_sections(scripts, &["pronouns".into()]), None);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn check_rules() {
+        let dir = write_scripts("rules", &[
+            ("lesson.tort", "#!include common/glossary.tort
+p[ie@ie]ce
+"),
+            ("common/glossary.tort", "#!rule ie = I before E #? except after C
+"),
+            ("twice.tort", "#!include common/glossary.tort
+#!include lesson.tort
+"),
+            ("unknown.tort", "w[ee|e@long]k
+"),
+            ("other.tort", "#!rule ie = In Europe
+"),
+        ]);
+        let mut loader = Loader::new();
+        let script = loader.parse(&dir.join("lesson.tort")).unwrap();
+        let scripts = loader.expand(script).unwrap();
+        assert!(loader.check_rules(&scripts).is_ok());
+        let script = loader.parse(&dir.join("twice.tort")).unwrap();
+        let scripts = loader.expand(script).unwrap();
+        assert!(loader.check_rules(&scripts).is_ok());
+        let script = loader.parse(&dir.join("unknown.tort")).unwrap();
+        let scripts = loader.expand(script).unwrap();
+        let err = loader.check_rules(&scripts).unwrap_err();
+        assert_eq!(err.to_string(), "unknown rule `@long`");
+        let script = loader.parse(&dir.join("other.tort")).unwrap();
+        let scripts = loader.expand(script).unwrap();
+        let err = loader.check_rules(&scripts).unwrap_err();
+        assert_eq!(err.to_string(), "rule `@ie` is defined twice");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
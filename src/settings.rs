@@ -0,0 +1,195 @@
+use clap::ValueEnum;
+
+use crate::args::{AnswerMode, Args, CompareRule, Direction, ExplainMode};
+
+/// Keys of settings that can be given with `#!set key = value` directive.
+pub const KEYS: &[&str] = &["random", "tests", "answer", "compare", "compare-plain", "compare-translation",
+                            "compare-complex", "typos", "direction", "retry-wrong", "explain",
+                            "stress"];
+
+/// A setting given with `#!set key = value` directive in a script.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Setting {
+    Random(bool),
+    Tests(usize),
+    Answer(AnswerMode),
+    Compare(Vec<CompareRule>),
+    ComparePlain(Vec<CompareRule>),
+    CompareTranslation(Vec<CompareRule>),
+    CompareComplex(Vec<CompareRule>),
+    Typos(usize),
+    Direction(Direction),
+    RetryWrong(bool),
+    Explain(ExplainMode),
+    /// Whether the following plain lines with stress marks are stress questions, it's
+    /// applied by the parser
+    Stress(bool),
+}
+
+impl Setting {
+    /// Parse the value of the setting `key`. Returns a message about the error if the
+    /// key is unknown or the value has a wrong type.
+    pub fn parse(key: &str, value: &str) -> Result<Self, String> {
+        match key {
+            "random" => aid::parse_bool(value).map(Setting::Random),
+            "tests" => aid::parse_number(value).map(Setting::Tests),
+            "answer" => aid::parse_enum(value).map(Setting::Answer),
+            "compare" => aid::parse_rules(value).map(Setting::Compare),
+            "compare-plain" => aid::parse_rules(value).map(Setting::ComparePlain),
+            "compare-translation" => aid::parse_rules(value).map(Setting::CompareTranslation),
+            "compare-complex" => aid::parse_rules(value).map(Setting::CompareComplex),
+            "typos" => aid::parse_number(value).map(Setting::Typos),
+            "direction" => aid::parse_enum(value).map(Setting::Direction),
+            "retry-wrong" => aid::parse_bool(value).map(Setting::RetryWrong),
+            "explain" => aid::parse_enum(value).map(Setting::Explain),
+            "stress" => aid::parse_bool(value).map(Setting::Stress),
+            _ => Err(format!("unknown setting `{key}`"))
+        }
+    }
+}
+
+/// Settings of a quiz run. Settings that aren't given are `None`, so settings from
+/// the command line can take precedence over ones from scripts.
+#[derive(Debug, Default, PartialEq, Clone)]
+pub struct Settings {
+    pub random: Option<bool>,
+    pub tests: Option<usize>,
+    pub answer: Option<AnswerMode>,
+    pub compare: Option<Vec<CompareRule>>,
+    pub compare_plain: Option<Vec<CompareRule>>,
+    pub compare_translation: Option<Vec<CompareRule>>,
+    pub compare_complex: Option<Vec<CompareRule>>,
+    pub typos: Option<usize>,
+    pub direction: Option<Direction>,
+    pub retry_wrong: Option<bool>,
+    pub explain: Option<ExplainMode>,
+}
+
+impl Settings {
+    /// Settings given on the command line. Flags that are not set count as not given.
+    pub fn from_args(args: &Args) -> Self {
+        Self {
+            random: args.random.then_some(true),
+            tests: args.number_of_tests,
+            answer: args.answer,
+            compare: args.compare.clone(),
+            compare_plain: args.compare_plain.clone(),
+            compare_translation: args.compare_translation.clone(),
+            compare_complex: args.compare_complex.clone(),
+            typos: args.typos,
+            direction: args.direction(),
+            retry_wrong: args.retry_wrong.then_some(true),
+            explain: args.explain,
+        }
+    }
+
+    pub fn set(&mut self, setting: Setting) {
+        match setting {
+            Setting::Random(random) => self.random = Some(random),
+            Setting::Tests(tests) => self.tests = Some(tests),
+            Setting::Answer(answer) => self.answer = Some(answer),
+            Setting::Compare(rules) => self.compare = Some(rules),
+            Setting::ComparePlain(rules) => self.compare_plain = Some(rules),
+            Setting::CompareTranslation(rules) => self.compare_translation = Some(rules),
+            Setting::CompareComplex(rules) => self.compare_complex = Some(rules),
+            Setting::Typos(typos) => self.typos = Some(typos),
+            Setting::Direction(direction) => self.direction = Some(direction),
+            Setting::RetryWrong(retry_wrong) => self.retry_wrong = Some(retry_wrong),
+            Setting::Explain(explain) => self.explain = Some(explain),
+            Setting::Stress(_) => (),
+        }
+    }
+
+    /// Take the settings that aren't given here from `other` ones.
+    pub fn or(self, other: Self) -> Self {
+        Self {
+            random: self.random.or(other.random),
+            tests: self.tests.or(other.tests),
+            answer: self.answer.or(other.answer),
+            compare: self.compare.or(other.compare),
+            compare_plain: self.compare_plain.or(other.compare_plain),
+            compare_translation: self.compare_translation.or(other.compare_translation),
+            compare_complex: self.compare_complex.or(other.compare_complex),
+            typos: self.typos.or(other.typos),
+            direction: self.direction.or(other.direction),
+            retry_wrong: self.retry_wrong.or(other.retry_wrong),
+            explain: self.explain.or(other.explain),
+        }
+    }
+}
+
+pub(super) mod aid {
+    use super::*;
+
+    pub fn parse_bool(value: &str) -> Result<bool, String> {
+        match value {
+            "true" | "yes" | "on" => Ok(true),
+            "false" | "no" | "off" => Ok(false),
+            _ => Err(format!("`{value}` is not a boolean, expected `true` or `false`"))
+        }
+    }
+
+    pub fn parse_number(value: &str) -> Result<usize, String> {
+        value.parse().map_err(|_| format!("`{value}` is not a non-negative number"))
+    }
+
+    pub fn parse_enum<T: ValueEnum>(value: &str) -> Result<T, String> {
+        T::from_str(value, true).map_err(|_| {
+            let variants: Vec<String> = T::value_variants().iter()
+                .filter_map(|variant| variant.to_possible_value())
+                .map(|value| format!("`{}`", value.get_name()))
+                .collect();
+            format!("`{value}` is not a valid value, expected one of {}", variants.join(", "))
+        })
+    }
+
+    pub fn parse_rules(value: &str) -> Result<Vec<CompareRule>, String> {
+        value.split(',').map(|rule| parse_enum(rule.trim())).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn parse_settings() {
+        assert_eq!(Setting::parse("random", "true"), Ok(Setting::Random(true)));
+        assert_eq!(Setting::parse("tests", "20"), Ok(Setting::Tests(20)));
+        assert_eq!(Setting::parse("compare", "case, space"),
+                   Ok(Setting::Compare(vec![CompareRule::Case, CompareRule::Space])));
+        assert_eq!(Setting::parse("direction", "both"), Ok(Setting::Direction(Direction::Both)));
+        assert!(Setting::parse("random", "20").is_err());
+        assert!(Setting::parse("tests", "-1").is_err());
+        assert!(Setting::parse("answer", "all").is_err());
+        assert!(Setting::parse("shuffle", "true").is_err());
+        for key in KEYS {
+            assert!(!Setting::parse(key, "").unwrap_err().starts_with("unknown"));
+        }
+    }
+
+    #[test]
+    fn settings_precedence() {
+        let mut file = Settings::default();
+        file.set(Setting::Random(true));
+        file.set(Setting::Typos(1));
+        let cli = Settings { typos: Some(2), ..Default::default() };
+        let settings = cli.or(file);
+        assert_eq!(settings.random, Some(true));
+        assert_eq!(settings.typos, Some(2));
+        assert_eq!(settings.tests, None);
+    }
+
+    #[test]
+    fn answer_per_script() {
+        let mut gaps = Settings::default();
+        gaps.set(Setting::parse("answer", "gaps").unwrap());
+        let mut line = Settings::default();
+        line.set(Setting::parse("answer", "line").unwrap());
+        assert_eq!(Settings::default().or(gaps.clone()).answer, Some(AnswerMode::Gaps));
+        assert_eq!(Settings::default().or(line).answer, Some(AnswerMode::Line));
+        let cli = Settings { answer: Some(AnswerMode::Line), ..Default::default() };
+        assert_eq!(cli.or(gaps).answer, Some(AnswerMode::Line));
+    }
+}
@@ -1,7 +1,32 @@
+use std::collections::HashMap;
 use std::ops::Range;
+use std::path::Path;
 use std::sync::Arc;
 
 
 pub type Source<'source> = Arc<str>;
 pub type SourceRange = Range<usize>;
 
+/// Registry of sources of all loaded scripts, so any of them can be referred to by
+/// diagnostics. Sources are registered by their display names.
+#[derive(Debug, Default)]
+pub struct SourceMap {
+    sources: HashMap<String, Source<'static>>,
+}
+
+impl SourceMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Read the file and register its source.
+    pub fn load(&mut self, path: &Path) -> std::io::Result<Source<'static>> {
+        let source: Source = std::fs::read_to_string(path)?.into();
+        self.sources.insert(path.display().to_string(), source.clone());
+        Ok(source)
+    }
+
+    pub fn get(&self, name: &str) -> Option<Source<'static>> {
+        self.sources.get(name).cloned()
+    }
+}
@@ -2,10 +2,18 @@ use miette::Result;
 
 use crate::diag::Diag;
 use crate::lexis::{tok, Lexer, Token};
+use crate::settings::Setting;
+use crate::source::SourceRange;
 
 pub struct Parser<'source> {
+    source: &'source str,
     lexer: Lexer<'source>,
     cur_line: Vec<Lexeme>,
+    /// First token of the next line, lexed while looking for options of a choice question
+    next_token: Option<Token>,
+    /// Whether plain lines with stress marks are stress questions, set with
+    /// `#!set stress = true`
+    stress: bool,
     diag: Diag<'source>
 }
 
@@ -14,8 +22,11 @@ impl<'source> Parser<'source> {
         let mut lexer = Lexer::new(source_name, source);
         lexer.skip_comments(true);
         Parser {
+            source,
             lexer,
             cur_line: Vec::new(),
+            next_token: None,
+            stress: false,
             diag: Diag::new(source_name, source)
         }
     }
@@ -34,173 +45,757 @@ impl<'source> Parser<'source> {
 
     fn parse_line(&mut self) -> Result<Option<Line>> {
         self.cur_line.clear();
-        let token = self.lexer.lex()?;
+        let token = match self.next_token.take() {
+            Some(token) => token,
+            None => self.lexer.lex()?
+        };
         match token.kind() {
-            tok::pub_comment => {
-                if token.span().start == 0 {
-                    self.lexer.expect(tok::newline)?;
-                    Ok(Some(Line::PubComment(token)))
-                } else {
-                    self.lexer.expect_eol()?;
-                    Ok(Some(Line::PubComment(token)))
+            tok::pub_comment if self.directive(&token, "include").is_some() => {
+                self.lexer.expect_eol()?;
+                let path = self.directive(&token, "include").unwrap_or_default();
+                if path.is_empty() {
+                    return Err(self.diag.invalid_directive(token, "path of the included script is expected"));
+                }
+                Ok(Some(Line::Include { path: path.into(), span: token.span() }))
+            },
+            tok::pub_comment if self.directive(&token, "set").is_some() => {
+                self.lexer.expect_eol()?;
+                let args = self.directive(&token, "set").unwrap_or_default();
+                let Some((key, value)) = args.split_once('=') else {
+                    return Err(self.diag.invalid_directive(token, "`#!set key = value` is expected"));
+                };
+                match Setting::parse(key.trim(), value.trim()) {
+                    Ok(setting) => {
+                        if let Setting::Stress(stress) = setting {
+                            self.stress = stress;
+                        }
+                        Ok(Some(Line::Set(setting)))
+                    },
+                    Err(msg) => Err(self.diag.invalid_directive(token, &msg))
                 }
             },
-            tok::word | tok::punct | tok::number | tok::other | tok::colon |
-            tok::pipe | tok::l_square => {
+            tok::pub_comment if self.directive(&token, "rule").is_some() => {
+                let (token, explanation) = self.lexer.split_explanation(token);
+                let args = self.directive(&token, "rule").unwrap_or_default();
+                let Some((id, title)) = args.split_once('=') else {
+                    return Err(self.diag.invalid_directive(token, "`#!rule id = title` is expected"));
+                };
+                let id = id.trim().trim_start_matches('@');
+                if id.is_empty() || !id.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '-') {
+                    return Err(self.diag.invalid_directive(token, "id of the rule must be a word like `rule12`"));
+                }
+                self.lexer.expect_eol()?;
+                let explanation = explanation.map(|explanation| explanation.spelling().to_string());
+                let rule = Rule { id: id.into(), title: title.trim().into(), explanation, span: token.span() };
+                Ok(Some(Line::Rule(rule)))
+            },
+            tok::pub_comment if token.spelling().starts_with("ARGS:") => {
+                let msg = "`#!ARGS:` is replaced with `#!set key = value` directives, like `#!set answer = gaps`";
+                Err(self.diag.invalid_directive(token, msg))
+            },
+            tok::pub_comment => {
+                self.lexer.expect_eol()?;
+                Ok(Some(Line::PubComment(token)))
+            },
+            tok::word | tok::punct | tok::number | tok::other | tok::escape | tok::colon |
+            tok::pipe | tok::slash | tok::tag | tok::l_square | tok::l_brace => {
                 let stmt = self.parse_stmt(token)?;
                 Ok(Some(stmt))
             },
-            tok::arrow | tok::r_square => Err(self.diag.expected_text(token)),
-            tok::newline | tok::comment | tok::space => Ok(Some(Line::Empty)),
+            tok::heading => {
+                self.lexer.expect_eol()?;
+                let spelling = token.spelling();
+                let level = spelling.chars().take_while(|c| *c == '#').count() - 1;
+                let title = spelling[level + 1..].trim().to_string();
+                Ok(Some(Line::Heading { level, title }))
+            },
+            tok::arrow | tok::bi_arrow | tok::r_square | tok::r_brace => Err(self.diag.expected_text(token)),
+            // a comment of its own line is ignored even if it has an explanation
+            tok::newline | tok::comment | tok::explanation | tok::space => Ok(Some(Line::Empty)),
             tok::eof => Ok(None),
         }
     }
 
+    /// Arguments of the directive `name` if the public comment is such directive. The
+    /// name must follow `#!` without a space, so `#! set of verbs` is a usual comment.
+    fn directive(&self, token: &Token, name: &str) -> Option<&'source str> {
+        aid::directive(self.source[token.span()].strip_prefix("#!")?, name)
+    }
+
     fn parse_stmt(&mut self, first_token: Token) -> Result<Line> {
         match first_token.kind() {
             tok::l_square => return self.parse_complex_stmt(),
+            tok::l_brace => return self.parse_segment_stmt(),
             _ => self.cur_line.push(Lexeme::Normal(first_token))
         }
 
         loop {
             let token = self.lexer.lex()?;
             match token.kind() {
+                _ if self.is_stmt_end(&token) => {
+                    let text = aid::lexemes_to_text(&self.cur_line);
+                    self.cur_line.clear();
+                    let end = self.parse_stmt_end(token)?;
+                    if text.len() > 2 && text[0].spelling() == "?" && text[1].kind() == tok::space {
+                        return self.parse_choice_stmt(text, end);
+                    }
+                    return self.plain_stmt(text, end);
+                },
+                tok::l_brace => return self.parse_segment_stmt(),
                 _ if token.is_text() => self.cur_line.push(Lexeme::Normal(token.clone())),
-                _ if token.is_comment() | token.is_eol() => return self.parse_plain_stmt(token),
-                tok::arrow => return self.parse_translation_stmt(),
+                tok::arrow => return self.parse_translation_stmt(false),
+                tok::bi_arrow => return self.parse_translation_stmt(true),
                 tok::l_square => return self.parse_complex_stmt(),
                 _ => return Err(self.diag.expected_text(token))
             }
         }
     }
-    
-    fn parse_plain_stmt(&mut self, last_token: Token) -> Result<Line> {
-        let text = aid::lexemes_to_text(&self.cur_line);
-        self.cur_line.clear();
-        match last_token.kind() {
-            tok::pub_comment => Ok(Line::PlainStmt { text, comment: Some(last_token) }),
-            tok::comment | tok::newline | tok::eof => Ok(Line::PlainStmt { text, comment: None }),
-            _ => unreachable!()
+
+    /// Make a plain statement of the text, or a stress one if it has stress marks and
+    /// stress questions are turned on.
+    fn plain_stmt(&self, text: Text, end: StmtEnd) -> Result<Line> {
+        let StmtEnd { tags, comment, explanation } = end;
+        if self.stress && text.iter().any(|token| has_stress(token.spelling())) {
+            self.check_stress(&text)?;
+            return Ok(Line::StressStmt { text, tags, comment, explanation });
+        }
+        Ok(Line::PlainStmt { text, tags, comment, explanation })
+    }
+
+    /// Parse options of the choice question like `? question` on the following lines.
+    /// Right options start with `+`, and wrong ones start with `-`. The first line that
+    /// isn't an option ends the question, and the text is a plain statement if no
+    /// options follow it.
+    fn parse_choice_stmt(&mut self, text: Text, end: StmtEnd) -> Result<Line> {
+        let span = text[0].span().start..text[text.len() - 1].span().end;
+        let mut right_options = Vec::new();
+        let mut wrong_options = Vec::new();
+        loop {
+            let token = self.lexer.lex()?;
+            let right = match (token.kind(), token.spelling().chars().next()) {
+                (tok::other, Some('+')) => true,
+                (tok::punct, Some('-')) => false,
+                _ => {
+                    self.next_token = Some(token);
+                    break;
+                }
+            };
+            let mut token = match token.tail() {
+                Some(tail) => tail,
+                None => self.lexer.lex()?
+            };
+            while token.is_text() {
+                self.cur_line.push(Lexeme::Normal(token));
+                token = self.lexer.lex()?;
+            }
+            let option = aid::lexemes_to_text(&self.cur_line);
+            self.cur_line.clear();
+            if !token.is_eol() || option.is_empty() {
+                return Err(self.diag.unexpected_token(token, "text of the option"));
+            }
+            let options = if right { &mut right_options } else { &mut wrong_options };
+            options.push(option);
+            if token.kind() == tok::eof {
+                break;
+            }
         }
+        if right_options.is_empty() && wrong_options.is_empty() {
+            return self.plain_stmt(text, end);
+        }
+        let options: Vec<String> = right_options.iter().chain(&wrong_options).map(aid::spell).collect();
+        if right_options.is_empty() {
+            return Err(self.diag.invalid_choice(span, "a right option like `+ option` is expected after it"));
+        }
+        if options.len() < 2 {
+            return Err(self.diag.invalid_choice(span, "it must have at least two options"));
+        }
+        if let Some(i) = (1..options.len()).find(|i| options[..*i].contains(&options[*i])) {
+            return Err(self.diag.invalid_choice(span, &format!("option `{}` is given twice", options[i])));
+        }
+        let question = text[2..].to_vec();
+        let StmtEnd { tags, comment, explanation } = end;
+        Ok(Line::ChoiceStmt { question, right_options, wrong_options, tags, comment, explanation })
     }
 
-    fn parse_translation_stmt(&mut self) -> Result<Line> {
+    /// Check that there is not more than one stress mark in every word of the text.
+    fn check_stress(&self, text: &Text) -> Result<()> {
+        for token in text {
+            let spelling = token.spelling();
+            if spelling.chars().count() - unstressed(spelling).chars().count() > 1 {
+                return Err(self.diag.invalid_stress(token, "a word can have only one stress"));
+            }
+        }
+        Ok(())
+    }
+
+    fn parse_translation_stmt(&mut self, bidirectional: bool) -> Result<Line> {
         let original: Text = aid::lexemes_to_text(&self.cur_line);
         self.cur_line.clear();
+        let mut translations = Vec::new();
         loop {
             let token = self.lexer.lex()?;
-            if token.is_text() {
+            if token.kind() == tok::pipe {
+                self.take_translation(&mut translations, &token)?;
+            } else if self.is_stmt_end(&token) {
+                self.take_translation(&mut translations, &token)?;
+                let StmtEnd { tags, comment, explanation } = self.parse_stmt_end(token)?;
+                return Ok(Line::TranslationStmt { original, translations, bidirectional, tags, comment, explanation })
+            } else if token.is_text() {
                 self.cur_line.push(Lexeme::Normal(token));
-            } else if token.kind() == tok::pub_comment {
-                let translation: Text = aid::lexemes_to_text(&self.cur_line);
-                self.cur_line.clear();
-                self.lexer.expect_eol()?;
-                return Ok(Line::TranslationStmt { original, translation, comment: Some(token) })
-            } else if token.is_eol() {
-                let translation: Text = aid::lexemes_to_text(&self.cur_line);
-                self.cur_line.clear();
-                return Ok(Line::TranslationStmt { original, translation, comment: None })
+            } else if token.kind() == tok::l_square && translations.is_empty() {
+                let original = original.into_iter().map(Lexeme::Normal).collect();
+                let ortho = self.parse_orthogram()?;
+                self.cur_line.push(Lexeme::Orthogram(ortho));
+                return self.parse_complex_translation_stmt(original, bidirectional);
             } else {
                 return Err(self.diag.expected_text(token));
             }
         }
     }
 
+    /// Parse the translation of a line which has orthograms in one of its sides. The
+    /// current line is the beginning of the translation. Such line has only one translation.
+    fn parse_complex_translation_stmt(&mut self, original: Vec<Lexeme>, bidirectional: bool) -> Result<Line> {
+        loop {
+            let token = self.lexer.lex()?;
+            if self.is_stmt_end(&token) {
+                let translation: Vec<Lexeme> = aid::strip(self.cur_line.drain(0..self.cur_line.len()).collect());
+                if translation.is_empty() {
+                    return Err(self.diag.expected_text(token));
+                }
+                let StmtEnd { tags, comment, explanation } = self.parse_stmt_end(token)?;
+                return Ok(Line::ComplexTranslationStmt { original, translation, bidirectional, tags, comment,
+                                                         explanation });
+            } else if token.kind() == tok::pipe {
+                return Err(self.diag.unexpected_token(token, "text of the only translation of a line with orthograms"));
+            } else if token.is_text() {
+                self.cur_line.push(Lexeme::Normal(token));
+            } else if token.kind() == tok::l_square {
+                let ortho = self.parse_orthogram()?;
+                self.cur_line.push(Lexeme::Orthogram(ortho));
+            } else {
+                return Err(self.diag.expected_text(token));
+            }
+        }
+    }
+
+    /// Move the current line into the list of accepted translations. Empty translation
+    /// is allowed only if it's the only one.
+    fn take_translation(&mut self, translations: &mut Vec<Text>, last_token: &Token) -> Result<()> {
+        let translation: Text = aid::lexemes_to_text(&self.cur_line);
+        self.cur_line.clear();
+        if translation.is_empty() && (last_token.kind() == tok::pipe || !translations.is_empty()) {
+            return Err(self.diag.expected_text(last_token.clone()));
+        }
+        translations.push(translation);
+        Ok(())
+    }
+
     fn parse_complex_stmt(&mut self) -> Result<Line> {
         let ortho = self.parse_orthogram()?;
         self.cur_line.push(Lexeme::Orthogram(ortho));
         loop {
             let token = self.lexer.lex()?;
-            if token.is_text() {
-                self.cur_line.push(Lexeme::Normal(token));
-            } else if token.kind() == tok::pub_comment {
-                self.lexer.expect_eol()?;
+            if self.is_stmt_end(&token) {
                 let text: Vec<Lexeme> = aid::strip(self.cur_line.drain(0..self.cur_line.len()).collect());
-                return Ok(Line::ComplexStmt { text, comment: Some(token) });
-            } else if token.is_eol() {
-                let text: Vec<Lexeme> = aid::strip(self.cur_line.drain(0..self.cur_line.len()).collect());
-                return Ok(Line::ComplexStmt { text, comment: None });
+                let StmtEnd { tags, comment, explanation } = self.parse_stmt_end(token)?;
+                return Ok(Line::ComplexStmt { text, tags, comment, explanation });
+            } else if token.is_text() {
+                self.cur_line.push(Lexeme::Normal(token));
             } else if token.kind() == tok::l_square {
                 let ortho = self.parse_orthogram()?;
                 self.cur_line.push(Lexeme::Orthogram(ortho));
+            } else if token.kind() == tok::arrow {
+                return Err(self.diag.one_way_orthograms(token));
+            } else if token.kind() == tok::bi_arrow {
+                let original: Vec<Lexeme> = aid::strip(self.cur_line.drain(0..self.cur_line.len()).collect());
+                return self.parse_complex_translation_stmt(original, true);
             } else {
                 return Err(self.diag.expected_text(token));
             }
         }
     }
 
+    /// Parse a line with words split into segments. The current line is the text before
+    /// the first word, whose opening `{` is just lexed.
+    fn parse_segment_stmt(&mut self) -> Result<Line> {
+        let mut text: Vec<SegmentLexeme> = self.cur_line.drain(..)
+            .map(|lexeme| SegmentLexeme::Normal(lexeme.unwrap_norm().clone()))
+            .collect();
+        text.push(SegmentLexeme::Word(self.parse_segmented_word()?));
+        loop {
+            let token = self.lexer.lex()?;
+            let glued = !matches!(text.last(), Some(SegmentLexeme::Normal(last)) if last.kind() == tok::space);
+            if token.kind() == tok::tag && glued {
+                text.push(SegmentLexeme::Normal(token));
+            } else if self.is_stmt_end(&token) {
+                while matches!(text.last(), Some(SegmentLexeme::Normal(last)) if last.kind() == tok::space) {
+                    text.pop();
+                }
+                let StmtEnd { tags, comment, explanation } = self.parse_stmt_end(token)?;
+                return Ok(Line::SegmentStmt { text, tags, comment, explanation });
+            } else if token.kind() == tok::l_brace {
+                text.push(SegmentLexeme::Word(self.parse_segmented_word()?));
+            } else if token.is_text() {
+                text.push(SegmentLexeme::Normal(token));
+            } else {
+                return Err(self.diag.expected_text(token));
+            }
+        }
+    }
+
+    /// Parse segments of a word like `{ма|ла|ко}` after its opening `{`.
+    fn parse_segmented_word(&mut self) -> Result<Vec<Text>> {
+        let start = self.lexer.offset() - 1;  // the opening `{` is just lexed
+        let mut segments = Vec::new();
+        let mut segment = Vec::new();
+        loop {
+            let token = self.lexer.lex()?;
+            match token.kind() {
+                tok::pipe => segments.push(std::mem::take(&mut segment)),
+                tok::r_brace => {
+                    segments.push(segment);
+                    break;
+                },
+                tok::space => {
+                    return Err(self.diag.invalid_segments(start..self.lexer.offset(), "a segment can't have spaces"));
+                },
+                _ if token.is_strict_text() && token.spelling().contains(BOUNDARY) => {
+                    let msg = format!("a segment can't have `{BOUNDARY}`, it separates segments in the answer");
+                    return Err(self.diag.invalid_segments(start..self.lexer.offset(), &msg));
+                },
+                _ if token.is_strict_text() => segment.push(token),
+                _ => return Err(self.diag.unexpected_token(token, "text of a segment, `|` or `}`"))
+            }
+        }
+        if segments.iter().any(Vec::is_empty) {
+            return Err(self.diag.invalid_segments(start..self.lexer.offset(), "a segment can't be empty"));
+        }
+        Ok(segments)
+    }
+
+    /// Whether a token is glued to the text of the current line, i.e. there is no space
+    /// between them.
+    fn is_glued(&self) -> bool {
+        self.cur_line.last()
+            .is_some_and(|lexeme| !matches!(lexeme, Lexeme::Normal(token) if token.kind() == tok::space))
+    }
+
+    /// Whether the token starts the end of a statement, see [`Parser::parse_stmt_end`].
+    fn is_stmt_end(&self, token: &Token) -> bool {
+        match token.kind() {
+            tok::tag => !self.is_glued() && self.lexer.only_tags_left(),
+            tok::comment | tok::pub_comment | tok::explanation => true,
+            _ => token.is_eol()
+        }
+    }
+
+    /// Parse the end of a statement starting with the token: tags, a public comment,
+    /// an explanation and the end of line. Every part except the last one is optional.
+    fn parse_stmt_end(&mut self, mut token: Token) -> Result<StmtEnd> {
+        let mut end = StmtEnd::default();
+        while token.kind() == tok::tag || (token.kind() == tok::space && !end.tags.is_empty()) {
+            if token.kind() == tok::tag {
+                end.tags.push(token.spelling()[1..].to_string());
+            }
+            token = self.lexer.lex()?;
+        }
+        if token.is_comment() {
+            // a usual comment gets here only if it has an explanation
+            let (comment, explanation) = self.lexer.split_explanation(token);
+            if comment.kind() == tok::pub_comment {
+                end.comment = Some(comment);
+            }
+            token = match explanation {
+                Some(explanation) => explanation,
+                None => self.lexer.lex()?
+            };
+        }
+        if token.kind() == tok::explanation {
+            end.explanation = Some(token);
+            token = self.lexer.lex()?;
+        }
+        if token.is_eol() {
+            Ok(end)
+        } else if end.tags.is_empty() || end.comment.is_some() || end.explanation.is_some() {
+            Err(self.diag.expected_eol(token))
+        } else {
+            Err(self.diag.unexpected_token(token, "a tag or end of line"))
+        }
+    }
+
     fn parse_orthogram(&mut self) -> Result<Orthogram> {
+        let start = self.lexer.offset() - 1;  // the opening `[` is just lexed
+        let orthogram = self.parse_orthogram_variants()?;
+        let variants: Vec<String> = match &orthogram {
+            Orthogram::Gap { answers, .. } => answers.iter().map(aid::spell).collect(),
+            Orthogram::Choice { right_answers, wrong_answers, .. } => {
+                right_answers.iter().chain(wrong_answers).map(aid::spell).collect()
+            }
+        };
+        for (i, variant) in variants.iter().enumerate() {
+            let blank = variant.trim().is_empty();
+            if !blank && (variant.starts_with(char::is_whitespace) || variant.ends_with(char::is_whitespace)) {
+                let msg = format!("variant `{variant}` has spaces around it, only a variant of spaces can have them");
+                return Err(self.diag.invalid_orthogram(start..self.lexer.offset(), &msg));
+            }
+            if variants[..i].contains(variant) {
+                let msg = format!("variant {} is given twice", visible(variant));
+                return Err(self.diag.invalid_orthogram(start..self.lexer.offset(), &msg));
+            }
+        }
+        Ok(orthogram)
+    }
+
+    fn parse_orthogram_variants(&mut self) -> Result<Orthogram> {
+        let mut answers = Vec::new();
         let mut answer = Vec::new();
         loop {
             let token = self.lexer.lex()?;
-            if token.is_strict_text() {
+            if matches!(token.kind(), tok::tag | tok::colon | tok::r_square) {
+                answers.push(answer);
+                let OrthogramEnd { rule, comment, explanation } = self.parse_orthogram_end(token)?;
+                return Ok(Orthogram::Gap { answers, rule, comment, explanation });
+            } else if token.is_strict_text() {
                 answer.push(token);
-            } else if token.kind() == tok::colon {
-                let mut comment = Vec::new();
-                let mut token = self.lexer.lex()?;
-                loop {
-                    if token.is_text() {
-                        comment.push(token);
-                    } else {
-                        break;
-                    }
-                    token = self.lexer.lex()?;
-                }
-                if token.kind() != tok::r_square {
-                    return Err(self.diag.unexpected_token(token, "`]`"));
-                }
-                return Ok(Orthogram::Gap { answer, comment: Some(comment) });
-            } else if token.kind() == tok::r_square {
-                return Ok(Orthogram::Gap { answer, comment: None });
+            } else if token.kind() == tok::slash {
+                answers.push(std::mem::take(&mut answer));
             } else if token.kind() == tok::pipe {
-                return self.parse_choice_orthogram(answer);
+                answers.push(answer);
+                return self.parse_choice_orthogram(answers);
             } else {
                 return Err(self.diag.expected_text(token));
             }
         }
     }
 
-    fn parse_choice_orthogram(&mut self, right_answer: Text) -> Result<Orthogram> {
+    fn parse_choice_orthogram(&mut self, right_answers: Vec<Text>) -> Result<Orthogram> {
         let mut wrong_answers = Vec::new();
         loop {
             let mut wrong_answer = Vec::new();
             loop {
                 let token = self.lexer.lex()?;
-                if token.is_strict_text() {
+                if matches!(token.kind(), tok::tag | tok::colon | tok::r_square) {
+                    wrong_answers.push(wrong_answer);
+                    let OrthogramEnd { rule, comment, explanation } = self.parse_orthogram_end(token)?;
+                    return Ok(Orthogram::Choice { right_answers, wrong_answers, rule, comment, explanation });
+                } else if token.is_strict_text() {
                     wrong_answer.push(token);
                 } else if token.kind() == tok::pipe {
                     wrong_answers.push(wrong_answer);
                     break;
-                } else if token.kind() == tok::r_square {
-                    wrong_answers.push(wrong_answer);
-                    return Ok(Orthogram::Choice { right_answer, wrong_answers });
                 } else {
-                    return Err(self.diag.unexpected_token(token, "`|` or `]`"));
+                    return Err(self.diag.unexpected_token(token, "`|`, `:` or `]`"));
                 }
             }
         }
     }
+
+    /// Parse the end of an orthogram starting with the token: a reference to a rule
+    /// like `@rule12`, and a hint after `:` till the closing `]`. The second `:` starts
+    /// its explanation, so `[ie::explanation]` has an explanation but no hint.
+    fn parse_orthogram_end(&mut self, mut token: Token) -> Result<OrthogramEnd> {
+        let mut end = OrthogramEnd::default();
+        if token.kind() == tok::tag {
+            end.rule = Some(token);
+            token = self.lexer.lex()?;
+        }
+        match token.kind() {
+            tok::r_square => return Ok(end),
+            tok::colon => (),
+            _ => return Err(self.diag.unexpected_token(token, "`:` or `]`"))
+        }
+        let mut comment = Vec::new();
+        let mut explanation = None;
+        let mut token = self.lexer.lex()?;
+        loop {
+            if token.kind() == tok::colon && explanation.is_none() {
+                explanation = Some(Vec::new());
+            } else if token.is_text() {
+                explanation.as_mut().unwrap_or(&mut comment).push(token);
+            } else {
+                break;
+            }
+            token = self.lexer.lex()?;
+        }
+        if token.kind() != tok::r_square {
+            return Err(self.diag.unexpected_token(token, "`]`"));
+        }
+        end.comment = Some(comment).filter(|comment| !comment.is_empty());
+        end.explanation = explanation;
+        Ok(end)
+    }
+}
+
+/// Optional ending of a statement line.
+#[derive(Default)]
+struct StmtEnd {
+    tags: Vec<String>,
+    comment: Option<Token>,
+    explanation: Option<Token>,
+}
+
+/// Optional ending of an orthogram.
+#[derive(Default)]
+struct OrthogramEnd {
+    rule: Option<Token>,
+    comment: Option<Text>,
+    explanation: Option<Text>,
 }
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum Line {
     PubComment(Token),
+    /// Heading of a section, `## Title` has level 1, `### Title` has level 2 and so on
+    Heading {
+        level: usize,
+        title: String,
+    },
+    /// `#!set key = value` directive
+    Set(Setting),
+    /// `#!rule id = title` directive defining a rule orthograms can refer to
+    Rule(Rule),
+    /// `#!include path` directive, the path is relative to the script
+    Include {
+        path: String,
+        span: SourceRange,
+    },
     PlainStmt {
         text: Text,
+        tags: Vec<String>,
         comment: Option<Token>,
+        /// Explanation shown after the answer
+        explanation: Option<Token>,
     },
     TranslationStmt {
         original: Text,
-        translation: Text,
+        /// Accepted translations, the first one is canonical
+        translations: Vec<Text>,
+        /// Whether the line can be asked in reverse direction (it's written with `<->`)
+        bidirectional: bool,
+        tags: Vec<String>,
         comment: Option<Token>,
+        /// Explanation shown after the answer
+        explanation: Option<Token>,
     },
     ComplexStmt {
         text: Vec<Lexeme>,
+        tags: Vec<String>,
+        comment: Option<Token>,
+        /// Explanation shown after the answer
+        explanation: Option<Token>,
+    },
+    /// Plain line with stress marks (U+0301) after stressed vowels, where the stress
+    /// must be placed
+    StressStmt {
+        text: Text,
+        tags: Vec<String>,
         comment: Option<Token>,
+        /// Explanation shown after the answer
+        explanation: Option<Token>,
+    },
+    /// Translation line with orthograms in its original or translation, like `apple -> ябл[ы|і]к`
+    ComplexTranslationStmt {
+        original: Vec<Lexeme>,
+        translation: Vec<Lexeme>,
+        /// Whether the line can be asked in reverse direction (it's written with `<->`)
+        bidirectional: bool,
+        tags: Vec<String>,
+        comment: Option<Token>,
+        /// Explanation shown after the answer
+        explanation: Option<Token>,
+    },
+    /// Question followed by its options, like `? question` with `+ right` and `- wrong`
+    /// option lines
+    ChoiceStmt {
+        question: Text,
+        right_options: Vec<Text>,
+        wrong_options: Vec<Text>,
+        tags: Vec<String>,
+        comment: Option<Token>,
+        /// Explanation shown after the answer
+        explanation: Option<Token>,
+    },
+    /// Line with words split into segments, like `{ма|ла|ко}`, where the boundaries
+    /// must be placed
+    SegmentStmt {
+        text: Vec<SegmentLexeme>,
+        tags: Vec<String>,
+        comment: Option<Token>,
+        /// Explanation shown after the answer
+        explanation: Option<Token>,
     },
     Empty
 }
 
+impl Line {
+    pub fn is_stmt(&self) -> bool {
+        matches!(self, Line::PlainStmt { .. } | Line::TranslationStmt { .. } | Line::ComplexStmt { .. } |
+                       Line::StressStmt { .. } | Line::ComplexTranslationStmt { .. } | Line::SegmentStmt { .. } |
+                       Line::ChoiceStmt { .. })
+    }
+
+    /// Tags of the statement, other lines have no tags.
+    pub fn tags(&self) -> &[String] {
+        match self {
+            Line::PlainStmt { tags, .. } | Line::TranslationStmt { tags, .. } | Line::ComplexStmt { tags, .. } |
+            Line::StressStmt { tags, .. } | Line::ComplexTranslationStmt { tags, .. } |
+            Line::SegmentStmt { tags, .. } | Line::ChoiceStmt { tags, .. } => tags,
+            Line::PubComment(_) | Line::Heading { .. } | Line::Set(_) | Line::Rule(_) | Line::Include { .. } |
+            Line::Empty => &[]
+        }
+    }
+
+    /// Orthograms of the statement, in both sides of a translation.
+    pub fn orthograms(&self) -> Vec<&Orthogram> {
+        let lexemes: Vec<&Lexeme> = match self {
+            Line::ComplexStmt { text, .. } => text.iter().collect(),
+            Line::ComplexTranslationStmt { original, translation, .. } => original.iter().chain(translation).collect(),
+            _ => Vec::new()
+        };
+        lexemes.into_iter().filter_map(|lexeme| match lexeme {
+            Lexeme::Orthogram(orthogram) => Some(orthogram),
+            Lexeme::Normal(_) => None
+        }).collect()
+    }
+}
+
+/// Orthographic rule of a glossary. Orthograms refer to it with its id, like
+/// `[ie|ei@rule12]`, so answers can be counted by rules.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Rule {
+    pub id: String,
+    pub title: String,
+    /// Explanation of the rule given after `#?`
+    pub explanation: Option<String>,
+    /// Span of the directive defining the rule
+    pub span: SourceRange,
+}
+
+/// Selection of statements by their tags. A statement is selected if it has all tags
+/// of one of the `include` groups (or there are no groups), and has no `exclude` tags.
+#[derive(Debug, Default, PartialEq, Clone)]
+pub struct TagFilter {
+    pub include: Vec<Vec<String>>,
+    pub exclude: Vec<String>,
+}
+
+impl TagFilter {
+    /// Create filter from the command line values, where `a+b` means both `a` and `b`
+    /// tags, and several values are alternatives.
+    pub fn new(include: &[String], exclude: &[String]) -> Self {
+        let tag = |tag: &str| tag.trim().trim_start_matches('@').to_string();
+        Self {
+            include: include.iter().map(|group| group.split('+').map(tag).collect()).collect(),
+            exclude: exclude.iter().map(|name| tag(name)).collect(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty()
+    }
+
+    pub fn matches(&self, tags: &[String]) -> bool {
+        let included = self.include.is_empty() ||
+            self.include.iter().any(|group| group.iter().all(|tag| tags.contains(tag)));
+        included && !self.exclude.iter().any(|tag| tags.contains(tag))
+    }
+}
+
+/// Part of a script started with a heading and lasting until the next heading of
+/// the same or a higher level. The root section is the whole script, it has no title.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Section<L = Line> {
+    pub title: Option<String>,
+    pub level: usize,
+    /// Lines of the section before its first subsection
+    pub lines: Vec<L>,
+    pub sections: Vec<Section<L>>,
+    /// The heading line, it's kept to turn the tree back into the lines
+    heading: Option<L>,
+}
+
+/// Line of a section tree: a script line or a line of expanded scripts paired with
+/// the index of its script.
+pub trait SectionLine {
+    fn line(&self) -> &Line;
+}
+
+impl SectionLine for Line {
+    fn line(&self) -> &Line {
+        self
+    }
+}
+
+impl SectionLine for (usize, Line) {
+    fn line(&self) -> &Line {
+        &self.1
+    }
+}
+
+impl<L: SectionLine> Section<L> {
+    /// Build the section tree of the script lines.
+    pub fn new(lines: impl IntoIterator<Item = L>) -> Self {
+        let root = Section { title: None, level: 0, lines: Vec::new(), sections: Vec::new(), heading: None };
+        let mut stack = vec![root];
+        for line in lines {
+            if let Line::Heading { level, title } = line.line() {
+                let (level, title) = (*level, title.clone());
+                while stack.len() > 1 && stack.last().unwrap().level >= level {
+                    let section = stack.pop().unwrap();
+                    stack.last_mut().unwrap().sections.push(section);
+                }
+                stack.push(Section { title: Some(title), level, lines: Vec::new(), sections: Vec::new(),
+                                     heading: Some(line) });
+            } else {
+                stack.last_mut().unwrap().lines.push(line);
+            }
+        }
+        while stack.len() > 1 {
+            let section = stack.pop().unwrap();
+            stack.last_mut().unwrap().sections.push(section);
+        }
+        stack.pop().unwrap()
+    }
+
+    /// Leave only sections whose titles contain one of the names (ignoring case) with
+    /// all their subsections. The lines of the script before its first statement are
+    /// kept, because they are its header. Returns `None` if no section matches.
+    pub fn select(self, names: &[String]) -> Option<Self> {
+        let names: Vec<String> = names.iter().map(|name| name.to_lowercase()).collect();
+        let sections: Vec<Section<L>> = self.sections.into_iter().filter_map(|s| s.select_by(&names)).collect();
+        if sections.is_empty() {
+            return None;
+        }
+        let lines = self.lines.into_iter().take_while(|line| !line.line().is_stmt()).collect();
+        Some(Section { lines, sections, ..self })
+    }
+
+    fn select_by(self, names: &[String]) -> Option<Self> {
+        let title = self.title.as_deref().unwrap_or_default().to_lowercase();
+        if names.iter().any(|name| title.contains(name.as_str())) {
+            return Some(self);
+        }
+        let sections: Vec<Section<L>> = self.sections.into_iter().filter_map(|s| s.select_by(names)).collect();
+        if sections.is_empty() {
+            None
+        } else {
+            Some(Section { lines: Vec::new(), sections, ..self })
+        }
+    }
+
+    /// Turn the tree back into the script lines.
+    pub fn flatten(self) -> Vec<L> {
+        let mut lines = Vec::new();
+        lines.extend(self.heading);
+        lines.extend(self.lines);
+        for section in self.sections {
+            lines.extend(section.flatten());
+        }
+        lines
+    }
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub enum Lexeme {
     Normal(Token),
@@ -225,21 +820,104 @@ impl Lexeme {
 
 pub type Text = Vec<Token>;
 
+/// Part of a segment line.
+#[derive(Debug, PartialEq, Clone)]
+pub enum SegmentLexeme {
+    Normal(Token),
+    /// Word split into segments, like `{ма|ла|ко}`
+    Word(Vec<Text>),
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub enum Orthogram {
     Gap {
-        answer: Text,
+        /// Accepted fillings of the gap, the first one is canonical
+        answers: Vec<Text>,
+        /// Reference to a rule, like `@rule12`
+        rule: Option<Token>,
         comment: Option<Text>,
+        /// Explanation shown after the answer
+        explanation: Option<Text>,
     },
     Choice {
-        right_answer: Text,
+        /// Right variants, the first one is canonical
+        right_answers: Vec<Text>,
         wrong_answers: Vec<Text>,
+        /// Reference to a rule, like `@rule12`
+        rule: Option<Token>,
+        comment: Option<Text>,
+        /// Explanation shown after the answer
+        explanation: Option<Text>,
+    }
+}
+
+/// Combining acute accent that marks the stressed vowel.
+pub const STRESS: char = '\u{301}';
+
+/// Boundary of segments the user places into a word of a segment line.
+pub const BOUNDARY: char = '-';
+
+pub fn is_vowel(c: char) -> bool {
+    c.to_lowercase().all(|c| "aeiouyаеёиоуыэюяіїє".contains(c))
+}
+
+/// Whether the word has a stress mark, that is [`STRESS`] right after a vowel. The
+/// mark after other letters is a usual diacritic, like in `ń`.
+pub fn has_stress(word: &str) -> bool {
+    word.chars().zip(word.chars().skip(1)).any(|(prev, c)| c == STRESS && is_vowel(prev))
+}
+
+/// The word without its stress marks, other diacritics are kept.
+pub fn unstressed(word: &str) -> String {
+    let mut spelling = String::new();
+    let mut prev = None;
+    for c in word.chars() {
+        if !(c == STRESS && prev.is_some_and(is_vowel)) {
+            spelling.push(c);
+        }
+        prev = Some(c);
+    }
+    spelling
+}
+
+/// Spelling of an orthogram variant where an empty variant is shown as `∅`, and
+/// spaces of a variant made only of them are shown as `␣`.
+pub fn visible(variant: &str) -> String {
+    if variant.is_empty() {
+        "\u{2205}".into()
+    } else if variant.trim().is_empty() {
+        variant.chars().map(|_| '\u{2423}').collect()
+    } else {
+        variant.into()
+    }
+}
+
+impl Orthogram {
+    /// Reference to a rule, like `@rule12`.
+    pub fn rule(&self) -> Option<&Token> {
+        match self {
+            Orthogram::Gap { rule, .. } | Orthogram::Choice { rule, .. } => rule.as_ref()
+        }
     }
 }
 
 pub(super) mod aid {
     use super::*;
 
+    /// Arguments of the directive `name` if the public comment is such directive.
+    pub fn directive<'a>(comment: &'a str, name: &str) -> Option<&'a str> {
+        let args = comment.strip_prefix(name)?;
+        if args.is_empty() || args.starts_with(char::is_whitespace) {
+            Some(args.trim())
+        } else {
+            None
+        }
+    }
+
+    pub fn spell(text: &Text) -> String {
+        text.iter().map(|token| token.spelling()).collect()
+    }
+
     pub fn lexemes_to_text(lexemes: &Vec<Lexeme>) -> Text {
         let mut text = Vec::new();
         let lexemes = aid::strip(lexemes.clone());
@@ -253,26 +931,14 @@ pub(super) mod aid {
         text
     }
     
+    /// Strip spaces around the text. Orthograms at its ends are kept, and the text of
+    /// only spaces becomes empty.
     pub fn strip(lexemes: Vec<Lexeme>) -> Vec<Lexeme> {
-        let mut beginning_spaces = 0;
-        for lexeme in &lexemes {
-            if let Lexeme::Normal(token) = lexeme {
-                if token.kind() == tok::space {
-                    beginning_spaces += 1;
-                } else {
-                    break;
-                }
-            }
-        }
-        let mut ending_spaces = lexemes.len();
-        for lexeme in lexemes.iter().rev() {
-            if let Lexeme::Normal(token) = lexeme {
-                if token.kind() == tok::space {
-                    ending_spaces -= 1;
-                } else {
-                    break;
-                }
-            }
+        let is_space = |lexeme: &&Lexeme| matches!(lexeme, Lexeme::Normal(token) if token.kind() == tok::space);
+        let beginning_spaces = lexemes.iter().take_while(is_space).count();
+        let ending_spaces = lexemes.len() - lexemes.iter().rev().take_while(is_space).count();
+        if beginning_spaces >= ending_spaces {
+            return Vec::new();
         }
         lexemes[beginning_spaces..ending_spaces].to_vec()
     }
@@ -321,13 +987,50 @@ mod tests {
     fn parse_translation() {
         let mut parser = Parser::new("test", "hello -> world\n");
         let line = parser.parse_line().unwrap().unwrap();
-        if let Line::TranslationStmt { original, translation, comment } = line {
+        if let Line::TranslationStmt { original, translations, bidirectional, tags, comment, .. } = line {
             assert_eq!(original.len(), 1);
-            assert_eq!(translation.len(), 1);
+            assert!(tags.is_empty());
+            assert_eq!(translations.len(), 1);
+            assert_eq!(translations[0].len(), 1);
+            assert!(!bidirectional);
             assert_eq!(comment, None);
         }
     }
 
+    #[test]
+    fn parse_bidirectional_translation() {
+        let mut parser = Parser::new("test", "hello <-> world\n");
+        let line = parser.parse_line().unwrap().unwrap();
+        if let Line::TranslationStmt { bidirectional, .. } = line {
+            assert!(bidirectional);
+        } else {
+            panic!("expected a translation statement");
+        }
+        let mut parser = Parser::new("test", "hello <-> world -> peace");
+        assert!(parser.parse_line().is_err());
+    }
+
+    #[test]
+    fn parse_several_translations() {
+        let mut parser = Parser::new("test", "hello -> salut | bonjour tout |allo #! hint\n");
+        let line = parser.parse_line().unwrap().unwrap();
+        if let Line::TranslationStmt { original, translations, comment, .. } = line {
+            assert_eq!(original.len(), 1);
+            assert_eq!(translations.len(), 3);
+            assert_eq!(translations[1].len(), 3);
+            assert!(comment.is_some());
+        } else {
+            panic!("expected a translation statement");
+        }
+
+        let mut parser = Parser::new("test", "hello -> salut | | bonjour");
+        assert!(parser.parse_line().is_err());
+        let mut parser = Parser::new("test", "hello -> salut |");
+        assert!(parser.parse_line().is_err());
+        let mut parser = Parser::new("test", "hello ->");
+        assert!(parser.parse_line().is_ok());
+    }
+
     #[test]
     fn parse_gap_orthogram() {
         let mut parser = Parser::new("test", "[a] hello [b] world [c:comment]");
@@ -340,6 +1043,486 @@ mod tests {
         let _ = parser.parse_line().unwrap().unwrap();
     }
 
+    #[test]
+    fn parse_right_variants() {
+        let mut parser = Parser::new("test", "во[ў/у]к [ee/ea|e|ie]k/s [a/:hint]");
+        let line = parser.parse_line().unwrap().unwrap();
+        let Line::ComplexStmt { text, .. } = line else {
+            panic!("expected an orthogram statement");
+        };
+        let Orthogram::Gap { answers, .. } = text[1].unwrap_orthogram() else {
+            panic!("expected a gap orthogram");
+        };
+        assert_eq!(answers.len(), 2);
+        assert_eq!(answers[1][0].spelling(), "у");
+        let Orthogram::Choice { right_answers, wrong_answers, .. } = text[4].unwrap_orthogram() else {
+            panic!("expected a choice orthogram");
+        };
+        assert_eq!(right_answers.len(), 2);
+        assert_eq!(wrong_answers.len(), 2);
+        assert_eq!(text[6].unwrap_norm().spelling(), "/");
+        let Orthogram::Gap { answers, comment, .. } = text[9].unwrap_orthogram() else {
+            panic!("expected a gap orthogram");
+        };
+        assert_eq!(answers.len(), 2);
+        assert!(answers[1].is_empty());
+        assert!(comment.is_some());
+
+        let mut parser = Parser::new("test", "[a|b/c]");
+        assert!(parser.parse_line().is_err());
+    }
+
+    #[test]
+    fn parse_choice_hint() {
+        let mut parser = Parser::new("test", "w[ee|e|ie:long vowel]k");
+        let line = parser.parse_line().unwrap().unwrap();
+        let Line::ComplexStmt { text, .. } = line else {
+            panic!("expected an orthogram statement");
+        };
+        let Orthogram::Choice { wrong_answers, comment, .. } = text[1].unwrap_orthogram() else {
+            panic!("expected a choice orthogram");
+        };
+        assert_eq!(wrong_answers.len(), 2);
+        let comment: String = comment.as_ref().unwrap().iter().map(|token| token.spelling()).collect();
+        assert_eq!(comment, "long vowel");
+
+        let mut parser = Parser::new("test", "w[ee|e:hint\nk]");
+        assert!(parser.parse_line().is_err());
+    }
+
+    #[test]
+    fn parse_explanations() {
+        let source = "hello -> salut @words #! hint #? explanation\nw[ee::long vowel]k [ie:hint:rule] #?\n#? wrong\n";
+        let mut parser = Parser::new("test", source);
+        let line = parser.parse_line().unwrap().unwrap();
+        let Line::TranslationStmt { tags, comment, explanation, .. } = line else {
+            panic!("expected a translation statement");
+        };
+        assert_eq!(tags, ["words"]);
+        assert_eq!(comment.unwrap().spelling(), "hint");
+        assert_eq!(explanation.unwrap().spelling(), "explanation");
+
+        let line = parser.parse_line().unwrap().unwrap();
+        let Line::ComplexStmt { text, explanation, .. } = line else {
+            panic!("expected an orthogram statement");
+        };
+        assert_eq!(explanation.unwrap().spelling(), "");
+        let Orthogram::Gap { comment, explanation, .. } = text[1].unwrap_orthogram() else {
+            panic!("expected a gap orthogram");
+        };
+        assert_eq!(*comment, None);
+        let explanation: String = explanation.as_ref().unwrap().iter().map(|token| token.spelling()).collect();
+        assert_eq!(explanation, "long vowel");
+        let Orthogram::Gap { comment, explanation, .. } = text[4].unwrap_orthogram() else {
+            panic!("expected a gap orthogram");
+        };
+        assert_eq!(comment.as_ref().unwrap()[0].spelling(), "hint");
+        assert_eq!(explanation.as_ref().unwrap()[0].spelling(), "rule");
+        assert_eq!(parser.parse_line().unwrap(), Some(Line::Empty));
+
+        let source = "# is this right? #?\n# a #? b\n#! note #? x\nhello # comment #? explanation\n";
+        let lines = Parser::new("test", source).parse().unwrap();
+        assert_eq!(lines[..4], [Line::Empty, Line::Empty, Line::Empty, Line::Empty]);
+        let Line::PubComment(comment) = &lines[4] else {
+            panic!("expected a public comment");
+        };
+        assert_eq!(comment.spelling(), "note #? x");
+        let Line::PlainStmt { comment, explanation, .. } = &lines[5] else {
+            panic!("expected a plain statement");
+        };
+        assert_eq!(*comment, None);
+        assert_eq!(explanation.as_ref().unwrap().spelling(), "explanation");
+
+        let mut parser = Parser::new("test", "[a:b:c:d] @x y\n");
+        let Some(Line::ComplexStmt { text, tags, .. }) = parser.parse_line().unwrap() else {
+            panic!("expected a complex statement");
+        };
+        assert_eq!(text[2].unwrap_norm().spelling(), "@x");
+        assert!(tags.is_empty());
+    }
+
+    #[test]
+    fn parse_rules() {
+        let source = "#!rule @ie = I before E #? except after C\np[ie@ie]ce w[ee|e@long:hint]k [a\\@b]\n";
+        let mut parser = Parser::new("test", source);
+        let rule = Rule { id: "ie".into(), title: "I before E".into(), explanation: Some("except after C".into()),
+                          span: 0..24 };
+        assert_eq!(parser.parse_line().unwrap(), Some(Line::Rule(rule)));
+        let Line::ComplexStmt { text, .. } = parser.parse_line().unwrap().unwrap() else {
+            panic!("expected an orthogram statement");
+        };
+        let Orthogram::Gap { rule, .. } = text[1].unwrap_orthogram() else {
+            panic!("expected a gap orthogram");
+        };
+        assert_eq!(rule.as_ref().unwrap().spelling(), "@ie");
+        let Orthogram::Choice { rule, comment, .. } = text[5].unwrap_orthogram() else {
+            panic!("expected a choice orthogram");
+        };
+        assert_eq!(rule.as_ref().unwrap().spelling(), "@long");
+        assert!(comment.is_some());
+        let Orthogram::Gap { answers, rule, .. } = text[8].unwrap_orthogram() else {
+            panic!("expected a gap orthogram");
+        };
+        assert_eq!(answers[0].len(), 3);
+        assert_eq!(*rule, None);
+
+        let errors = [
+            ("#!rule ie\n", "invalid directive: `#!rule id = title` is expected"),
+            ("#!rule = title\n", "invalid directive: id of the rule must be a word like `rule12`"),
+            ("#!rule i e = title\n", "invalid directive: id of the rule must be a word like `rule12`"),
+            ("[ie@rule x]\n", "unexpected token ` ` encountered instead of `:` or `]`"),
+            ("[ie@a|ei]\n", "unexpected token `|` encountered instead of `:` or `]`"),
+        ];
+        for (source, msg) in errors {
+            assert_eq!(Parser::new("test", source).parse().unwrap_err().to_string(), msg, "{source}");
+        }
+        let mut parser = Parser::new("test", "#! rule of thumb: read aloud\n");
+        assert!(matches!(parser.parse_line().unwrap(), Some(Line::PubComment(_))));
+    }
+
+    #[test]
+    fn strip() {
+        // orthograms at the ends are kept, only spaces are stripped
+        for source in ["[a] b \n", "b [a] \n"] {
+            let Some(Line::ComplexStmt { text, .. }) = Parser::new("test", source).parse_line().unwrap() else {
+                panic!("expected a statement with orthograms");
+            };
+            assert_eq!(text.len(), 3, "{source}");
+            assert_eq!(text.iter().filter(|lexeme| matches!(lexeme, Lexeme::Orthogram(_))).count(), 1, "{source}");
+        }
+        // a translation of only spaces is empty
+        let err = Parser::new("test", "[a] <->  \n").parse().unwrap_err();
+        assert_eq!(err.to_string(), "unexpected token `\n` instead of usual text");
+    }
+
+    #[test]
+    fn parse_complex_translations() {
+        let source = "apple -> ябл[ы|і]к @fruits\nво[ў|у]к <-> wolf\n[a]pple <-> [я]блык\n";
+        let mut parser = Parser::new("test", source);
+        let line = parser.parse_line().unwrap().unwrap();
+        assert_eq!(line.tags(), ["fruits"]);
+        assert_eq!(line.orthograms().len(), 1);
+        let Line::ComplexTranslationStmt { original, translation, bidirectional, .. } = line else {
+            panic!("expected a translation statement with orthograms");
+        };
+        assert_eq!(original.len(), 1);
+        assert_eq!(translation.len(), 3);
+        assert!(!bidirectional);
+        let line = parser.parse_line().unwrap().unwrap();
+        let Line::ComplexTranslationStmt { original, translation, bidirectional, .. } = line else {
+            panic!("expected a translation statement with orthograms");
+        };
+        assert_eq!(original.len(), 3);
+        assert_eq!(translation.len(), 1);
+        assert!(bidirectional);
+        assert_eq!(parser.parse_line().unwrap().unwrap().orthograms().len(), 2);
+
+        let errors = [
+            ("apple -> ябл[ы|і]к | яблык\n",
+             "unexpected token `|` encountered instead of text of the only translation of a line with orthograms"),
+            ("apple -> яблык | ябл[ы|і]к\n", "unexpected token `[` instead of usual text"),
+            ("[a]pple <->\n", "unexpected token `\n` instead of usual text"),
+            ("[a] <-> b -> c\n", "unexpected token `->` instead of usual text"),
+            ("[a]pple -> яблык\n",
+             "orthograms of the original are never asked in a line with `->`, use `<->` to ask it in reverse"),
+        ];
+        for (source, msg) in errors {
+            assert_eq!(Parser::new("test", source).parse().unwrap_err().to_string(), msg, "{source}");
+        }
+    }
+
+    #[test]
+    fn parse_blank_variants() {
+        let mut parser = Parser::new("test", "не[| |-]бо [ ]\n");
+        let Line::ComplexStmt { text, .. } = parser.parse_line().unwrap().unwrap() else {
+            panic!("expected an orthogram statement");
+        };
+        let Orthogram::Choice { right_answers, wrong_answers, .. } = text[1].unwrap_orthogram() else {
+            panic!("expected a choice orthogram");
+        };
+        assert!(right_answers[0].is_empty());
+        assert_eq!(aid::spell(&wrong_answers[0]), " ");
+        let Orthogram::Gap { answers, .. } = text[4].unwrap_orthogram() else {
+            panic!("expected a gap orthogram");
+        };
+        assert_eq!(aid::spell(&answers[0]), " ");
+        assert_eq!(visible(""), "\u{2205}");
+        assert_eq!(visible(" "), "\u{2423}");
+        assert_eq!(visible("a b"), "a b");
+
+        let spaces = |variant: &str| {
+            format!("invalid orthogram: variant `{variant}` has spaces around it, \
+                     only a variant of spaces can have them")
+        };
+        let errors = [
+            ("[a|b|a]\n", "invalid orthogram: variant a is given twice".to_string()),
+            ("[|-|]\n", "invalid orthogram: variant \u{2205} is given twice".to_string()),
+            ("[a/a]\n", "invalid orthogram: variant a is given twice".to_string()),
+            ("[ a|b]\n", spaces(" a")),
+            ("[a |b]\n", spaces("a ")),
+            ("[ a]\n", spaces(" a")),
+        ];
+        for (source, msg) in errors {
+            assert_eq!(Parser::new("test", source).parse().unwrap_err().to_string(), msg, "{source}");
+        }
+    }
+
+    #[test]
+    fn parse_stress() {
+        // accented words are a usual text until stress questions are turned on
+        let source = "Я люблю\u{301} малако\u{301}.\n#!set stress = true\nЯ люблю\u{301} малако\u{301}. @words\n\
+                      малако\n#!set stress = false\nкава\u{301}\n";
+        let mut parser = Parser::new("test", source);
+        assert!(matches!(parser.parse_line().unwrap(), Some(Line::PlainStmt { .. })));
+        assert_eq!(parser.parse_line().unwrap(), Some(Line::Set(Setting::Stress(true))));
+        let line = parser.parse_line().unwrap().unwrap();
+        assert_eq!(line.tags(), ["words"]);
+        let Line::StressStmt { text, .. } = line else {
+            panic!("expected a stress statement");
+        };
+        assert_eq!(text.len(), 6);
+        assert!(matches!(parser.parse_line().unwrap(), Some(Line::PlainStmt { .. })));
+        assert_eq!(parser.parse_line().unwrap(), Some(Line::Set(Setting::Stress(false))));
+        assert!(matches!(parser.parse_line().unwrap(), Some(Line::PlainStmt { .. })));
+        assert!(is_vowel('Я') && is_vowel('i') && !is_vowel('ў'));
+
+        for source in ["#!set stress = true\nмал\u{301}ако\n", "#!set stress = true\nPoznan\u{301}\n",
+                       "#!set stress = true\ns\u{301}wiat\n", "ма\u{301}лако\u{301}\n"] {
+            let lines = Parser::new("test", source).parse().unwrap();
+            assert!(matches!(lines.last().unwrap(), Line::PlainStmt { .. }), "{source}");
+        }
+        assert!(has_stress("малако\u{301}") && !has_stress("n\u{301}"));
+        assert_eq!(unstressed("ма\u{301}лан\u{301}"), "малан\u{301}");
+        let err = Parser::new("test", "#!set stress = true\nма\u{301}лако\u{301}\n").parse().unwrap_err();
+        assert_eq!(err.to_string(), "invalid stress mark: a word can have only one stress");
+    }
+
+    #[test]
+    fn parse_segments() {
+        let mut parser = Parser::new("test", "Syllables: {ма|ла|ко} і {дом}@x @words\nf(x)} -> y\n");
+        let line = parser.parse_line().unwrap().unwrap();
+        assert_eq!(line.tags(), ["words"]);
+        let Line::SegmentStmt { text, .. } = line else {
+            panic!("expected a segment statement");
+        };
+        assert_eq!(text.len(), 9);
+        let SegmentLexeme::Word(segments) = &text[3] else {
+            panic!("expected a segmented word");
+        };
+        let segments: Vec<String> = segments.iter().map(aid::spell).collect();
+        assert_eq!(segments, ["ма", "ла", "ко"]);
+        assert!(matches!(&text[7], SegmentLexeme::Word(segments) if segments.len() == 1));
+        assert!(matches!(&text[8], SegmentLexeme::Normal(token) if token.kind() == tok::tag));
+        let err = parser.parse_line().unwrap_err();
+        assert_eq!(err.to_string(), "unexpected token `}` instead of usual text");
+
+        let errors = [
+            ("{ма||ко}\n", "invalid segmented word: a segment can't be empty"),
+            ("{}\n", "invalid segmented word: a segment can't be empty"),
+            ("{ма|ла ко}\n", "invalid segmented word: a segment can't have spaces"),
+            ("{з|за-мяжы}\n", "invalid segmented word: a segment can't have `-`, it separates segments in the answer"),
+            ("{ма|ла\n", "unexpected token `\n` encountered instead of text of a segment, `|` or `}`"),
+            ("{ма|[a]}\n", "unexpected token `[` encountered instead of text of a segment, `|` or `}`"),
+            ("[a] {b}\n", "unexpected token `{` instead of usual text"),
+            ("a -> {b}\n", "unexpected token `{` instead of usual text"),
+            ("[{|b]\n", "unexpected token `{` instead of usual text"),
+        ];
+        for (source, msg) in errors {
+            assert_eq!(Parser::new("test", source).parse().unwrap_err().to_string(), msg, "{source}");
+        }
+        // escaped braces are a usual text
+        let lines = Parser::new("test", "f\\{x\\} = 1\n\\{a\\} -> b [\\{|\\}]\n").parse().unwrap();
+        let Line::PlainStmt { text, .. } = &lines[0] else {
+            panic!("expected a plain statement");
+        };
+        assert_eq!(aid::spell(text), "f{x} = 1");
+        assert!(matches!(lines[1], Line::ComplexTranslationStmt { .. }));
+        assert_eq!(lines[1].orthograms().len(), 1);
+    }
+
+    #[test]
+    fn parse_choices() {
+        let source = "? Which rule? @rules #? ie\n- E before I\n+ I before E\n+  the same \nhello\n? a\n+ b\n- c";
+        let mut parser = Parser::new("test", source);
+        let line = parser.parse_line().unwrap().unwrap();
+        assert_eq!(line.tags(), ["rules"]);
+        let Line::ChoiceStmt { question, right_options, wrong_options, explanation, .. } = line else {
+            panic!("expected a choice statement");
+        };
+        assert_eq!(aid::spell(&question), "Which rule?");
+        let right_options: Vec<String> = right_options.iter().map(aid::spell).collect();
+        assert_eq!(right_options, ["I before E", "the same"]);
+        assert_eq!(wrong_options.len(), 1);
+        assert!(explanation.is_some());
+        assert!(matches!(parser.parse_line().unwrap(), Some(Line::PlainStmt { .. })));
+        assert!(matches!(parser.parse_line().unwrap(), Some(Line::ChoiceStmt { .. })));
+        assert_eq!(parser.parse_line().unwrap(), None);
+
+        let mut parser = Parser::new("test", "? a\n+5%\n--\n-=\n");
+        let Some(Line::ChoiceStmt { right_options, wrong_options, .. }) = parser.parse_line().unwrap() else {
+            panic!("expected a choice statement");
+        };
+        assert_eq!(right_options.iter().map(aid::spell).collect::<Vec<_>>(), ["5%"]);
+        assert_eq!(wrong_options.iter().map(aid::spell).collect::<Vec<_>>(), ["-", "="]);
+
+        let errors = [
+            ("? a\n- b\n- c\n", "invalid choice question: a right option like `+ option` is expected after it"),
+            ("? a\n+ b\n", "invalid choice question: it must have at least two options"),
+            ("? a\n+ b\n- b\n", "invalid choice question: option `b` is given twice"),
+            ("? a\n+ b\n-\n", "unexpected token `\n` encountered instead of text of the option"),
+            ("? a\n+ [b]\n- c\n", "unexpected token `[` encountered instead of text of the option"),
+        ];
+        for (source, msg) in errors {
+            assert_eq!(Parser::new("test", source).parse().unwrap_err().to_string(), msg, "{source}");
+        }
+        for source in ["?a\n", "? Who are you?\nhello\n", "? a"] {
+            let lines = Parser::new("test", source).parse().unwrap();
+            assert!(matches!(lines[0], Line::PlainStmt { .. }), "{source}");
+        }
+    }
+
+    #[test]
+    fn parse_escapes() {
+        let mut parser = Parser::new("test", r"\[a\] \-> b -> c \| d \#");
+        let line = parser.parse_line().unwrap().unwrap();
+        if let Line::TranslationStmt { original, translations, .. } = line {
+            let original: String = original.iter().map(|token| token.spelling()).collect();
+            let translation: String = translations[0].iter().map(|token| token.spelling()).collect();
+            assert_eq!(original, "[a] -> b");
+            assert_eq!(translation, "c | d #");
+        } else {
+            panic!("expected a translation statement");
+        }
+
+        let mut parser = Parser::new("test", r"[\:|\|] w[\]]");
+        let line = parser.parse_line().unwrap().unwrap();
+        if let Line::ComplexStmt { text, .. } = line {
+            let Orthogram::Choice { right_answers, wrong_answers, .. } = text[0].unwrap_orthogram() else {
+                panic!("expected a choice orthogram");
+            };
+            assert_eq!(right_answers[0][0].spelling(), ":");
+            assert_eq!(wrong_answers[0][0].spelling(), "|");
+        } else {
+            panic!("expected an orthogram statement");
+        }
+    }
+
+    #[test]
+    fn parse_headings() {
+        let mut parser = Parser::new("test", "## Soft sign\n### After consonants  \nword ## comment\n");
+        assert_eq!(parser.parse_line().unwrap(), Some(Line::Heading { level: 1, title: "Soft sign".into() }));
+        assert_eq!(parser.parse_line().unwrap(), Some(Line::Heading { level: 2, title: "After consonants".into() }));
+        assert!(matches!(parser.parse_line().unwrap(), Some(Line::PlainStmt { .. })));
+    }
+
+    #[test]
+    fn section_tree() {
+        let source = "#! Header\na\n## First\nb\n### Inner\nc\n## Second\nd\n";
+        let lines = Parser::new("test", source).parse().unwrap();
+        let root = Section::new(lines.clone());
+        assert_eq!(root.title, None);
+        assert_eq!(root.lines.len(), 2);
+        assert_eq!(root.sections.len(), 2);
+        assert_eq!(root.sections[0].sections[0].title.as_deref(), Some("Inner"));
+        assert_eq!(root.clone().flatten(), lines);
+
+        let selected = root.clone().select(&["inner".into()]).unwrap();
+        assert_eq!(selected.lines.len(), 1);
+        assert_eq!(selected.sections[0].lines.len(), 0);
+        assert_eq!(selected.flatten().len(), 4);
+        let selected = root.clone().select(&["SECOND".into(), "first".into()]).unwrap();
+        assert_eq!(selected.flatten().len(), 7);
+        assert_eq!(root.select(&["third".into()]), None);
+    }
+
+    #[test]
+    fn parse_tags() {
+        let mut parser = Parser::new("test", "hello -> salut @words @easy #! hint\nw[ee|e]k @time\nmail a@b.c @x@y\n");
+        let line = parser.parse_line().unwrap().unwrap();
+        assert_eq!(line.tags(), ["words", "easy"]);
+        if let Line::TranslationStmt { translations, comment, .. } = line {
+            assert_eq!(translations[0].len(), 1);
+            assert!(comment.is_some());
+        } else {
+            panic!("expected a translation statement");
+        }
+        let line = parser.parse_line().unwrap().unwrap();
+        assert!(matches!(line, Line::ComplexStmt { .. }));
+        assert_eq!(line.tags(), ["time"]);
+        let line = parser.parse_line().unwrap().unwrap();
+        assert_eq!(line.tags(), ["x", "y"]);
+        if let Line::PlainStmt { text, .. } = line {
+            let text: String = text.iter().map(|token| token.spelling()).collect();
+            assert_eq!(text, "mail a@b.c");
+        } else {
+            panic!("expected a plain statement");
+        }
+
+        // a tag followed by a text is a usual text
+        let source = "Contact @john for help @people\n@all is here\nhello @x -> salut\n";
+        let lines = Parser::new("test", source).parse().unwrap();
+        let Line::PlainStmt { text, tags, .. } = &lines[0] else {
+            panic!("expected a plain statement");
+        };
+        assert_eq!(aid::spell(text), "Contact @john for help");
+        assert_eq!(tags, &["people"]);
+        let Line::PlainStmt { text, tags, .. } = &lines[1] else {
+            panic!("expected a plain statement");
+        };
+        assert_eq!(aid::spell(text), "@all is here");
+        assert!(tags.is_empty());
+        let Line::TranslationStmt { original, .. } = &lines[2] else {
+            panic!("expected a translation statement");
+        };
+        assert_eq!(aid::spell(original), "hello @x");
+    }
+
+    #[test]
+    fn tag_filter() {
+        let tags = ["verbs".to_string(), "hard".to_string()];
+        assert!(TagFilter::default().matches(&tags));
+        assert!(TagFilter::new(&["verbs+hard".into()], &[]).matches(&tags));
+        assert!(!TagFilter::new(&["verbs+easy".into()], &[]).matches(&tags));
+        assert!(TagFilter::new(&["verbs+easy".into(), "@hard".into()], &[]).matches(&tags));
+        assert!(!TagFilter::new(&["verbs".into()], &["hard".into()]).matches(&tags));
+        assert!(TagFilter::new(&[], &["easy".into()]).matches(&tags));
+    }
+
+    #[test]
+    fn parse_include() {
+        let source = "#!include words.tort \n#! included\n#! include the article\n#! include\n#!include\n";
+        let mut parser = Parser::new("test", source);
+        assert_eq!(parser.parse_line().unwrap(), Some(Line::Include { path: "words.tort".into(), span: 0..21 }));
+        assert!(matches!(parser.parse_line().unwrap(), Some(Line::PubComment(_))));
+        assert!(matches!(parser.parse_line().unwrap(), Some(Line::PubComment(_))));
+        assert!(matches!(parser.parse_line().unwrap(), Some(Line::PubComment(_))));
+        let err = parser.parse_line().unwrap_err();
+        assert!(err.to_string().contains("path of the included script is expected"), "{err}");
+    }
+
+    #[test]
+    fn parse_set() {
+        let mut parser = Parser::new("test", "#!set random = true\n#!set tests=20\n");
+        assert_eq!(parser.parse_line().unwrap(), Some(Line::Set(Setting::Random(true))));
+        assert_eq!(parser.parse_line().unwrap(), Some(Line::Set(Setting::Tests(20))));
+        let args = concat!("invalid directive: `#!ARGS:` is replaced with `#!set key = value` directives, ",
+                           "like `#!set answer = gaps`");
+        let errors = [
+            ("#!set random\n", "invalid directive: `#!set key = value` is expected"),
+            ("#!set random = 1\n", "invalid directive: `1` is not a boolean, expected `true` or `false`"),
+            ("#!set shuffle = true\n", "invalid directive: unknown setting `shuffle`"),
+            ("#!ARGS: -r\n", args),
+            ("#! ARGS: none\n", args),
+        ];
+        for (source, msg) in errors {
+            assert_eq!(Parser::new("test", source).parse().unwrap_err().to_string(), msg, "{source}");
+        }
+        let mut parser = Parser::new("test", "#!settings are here\n#! set of verbs\n#! ARGS are gone\n");
+        assert!(matches!(parser.parse_line().unwrap(), Some(Line::PubComment(_))));
+        assert!(matches!(parser.parse_line().unwrap(), Some(Line::PubComment(_))));
+        assert!(matches!(parser.parse_line().unwrap(), Some(Line::PubComment(_))));
+    }
+
     #[test]
     fn parse() {
         let mut parser = Parser::new("test", "#\n#!\n[a|b] hello [c:comment]\nhello->world");